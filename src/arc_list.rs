@@ -0,0 +1,146 @@
+/*
+A thread-safe persistent singly-linked stack
+--------------------------------------------
+
+This is `third::List` with `Arc` in place of `Rc`. The atomic reference
+counts cost a little on every `prepend` and `tail`, but in return a list is
+`Send` and `Sync` whenever `T` is, so a version can be handed to other
+threads while we keep working on our own:
+
+    main:      list -> A -> B -> C
+    worker 1:  list.tail() ---> B -> C
+    worker 2:  list.prepend(X) -> X -> A -> B -> C
+
+Nothing is copied, every thread shares the same nodes.
+
+There's only one implementation, `third::List` is generic over the kind of
+pointer it shares nodes through. An `Arc` list starts out from `new()` in
+this module, `default()` or collecting. `List::new()` is reserved for `Rc`
+lists, a second one for `Arc` would leave every plain `third::List::new()`
+with two to pick from.
+*/
+
+use crate::third::{self, ArcFamily};
+
+/// The same persistent list as `third`, with its nodes behind `Arc`. Only
+/// the `Arc` flavour can cross threads, `Rc` lists stay where they are:
+///
+/// ```compile_fail
+/// fn is_send<T: Send>() {}
+/// is_send::<too_many_lists::third::List<i32>>();
+/// ```
+///
+/// ```compile_fail
+/// fn is_sync<T: Sync>() {}
+/// is_sync::<too_many_lists::arc_list::List<std::cell::Cell<i32>>>();
+/// ```
+pub type List<T> = third::List<T, ArcFamily>;
+pub type Iter<'a, T> = third::Iter<'a, T, ArcFamily>;
+pub type IntoIter<T> = third::IntoIter<T, ArcFamily>;
+
+pub fn new<T>() -> List<T> {
+    List::default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+
+    #[test]
+    fn basics() {
+        let list = super::new();
+        assert_eq!(list.head(), None);
+        assert_eq!(list, List::default());
+
+        let list = list.prepend(1).prepend(2).prepend(3);
+        assert_eq!(list.head(), Some(&3));
+        assert_eq!(list.tail().head(), Some(&2));
+
+        // Unique nodes are moved out, shared ones cloned, same as with `Rc`
+        let shared = list.tail();
+        let elems: Vec<_> = list.into_iter().collect();
+        assert_eq!(elems, vec![3, 2, 1]);
+        assert_eq!(format!("{:?}", shared), "[2, 1]");
+    }
+
+    #[test]
+    fn send_sync() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<List<i32>>();
+        is_send_sync::<super::Iter<'static, i32>>();
+        is_send_sync::<super::IntoIter<i32>>();
+    }
+
+    #[test]
+    fn share_between_threads() {
+        use std::thread;
+
+        let list: List<_> = (0..100).collect();
+
+        // Each worker gets a snapshot for the price of a refcount bump, and
+        // builds its own versions on top of it
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let snapshot = list.clone();
+                thread::spawn(move || {
+                    let mine = snapshot.tail().prepend(1000 + i);
+                    assert_eq!(snapshot.iter().sum::<i32>(), 4950);
+                    mine
+                })
+            })
+            .collect();
+
+        for (i, worker) in workers.into_iter().enumerate() {
+            let mine = worker.join().unwrap();
+            assert_eq!(mine.head(), Some(&(1000 + i as i32)));
+            assert_eq!(mine.tail().head(), Some(&1));
+        }
+
+        // The workers' versions are gone and ours never changed
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            (0..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn long_list() {
+        // Dropping is iterative, even when the last handle goes on another
+        // thread
+        let n = if cfg!(miri) { 1_000 } else { 1_000_000 };
+        let list: List<_> = (0..n).collect();
+        std::thread::spawn(move || drop(list)).join().unwrap();
+    }
+
+    #[test]
+    fn racing_drops() {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        // Whichever thread lets go of the list last has to free all of it,
+        // without recursing
+        let (n, threads, rounds) = if cfg!(miri) {
+            (1_000, 2, 1)
+        } else {
+            (100_000, 8, 10)
+        };
+        for _ in 0..rounds {
+            let list: List<_> = (0..n).collect();
+            let barrier = Arc::new(Barrier::new(threads));
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    let list = list.clone();
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        drop(list);
+                    })
+                })
+                .collect();
+            drop(list);
+            for worker in workers {
+                worker.join().unwrap();
+            }
+        }
+    }
+}
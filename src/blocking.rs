@@ -0,0 +1,393 @@
+/*
+A blocking bounded queue
+------------------------
+
+A `fifth::List` behind a `Mutex`, with two `Condvar`s to park threads on:
+producers wait on `not_full` while the queue is at capacity, consumers wait
+on `not_empty` while it's empty. Every push wakes one consumer and every pop
+wakes one producer.
+
+Closing the queue wakes everybody up and drains it, handing whatever was left
+to the caller. From then on pushes fail and hand the element back, and pops
+report the queue as closed.
+*/
+
+use std::{
+    fmt,
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::fifth;
+
+pub struct BlockingQueue<T> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+struct State<T> {
+    list: fifth::List<T>,
+    // `fifth::List` doesn't keep track of its length
+    len: usize,
+    closed: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PushError<T> {
+    // The queue stayed full, the element is handed back
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PopError {
+    // The queue stayed empty
+    Empty,
+    // The queue was closed and everything in it has been popped
+    Closed,
+}
+
+// Neither the queue nor the channel ever leaves its state half updated while
+// the lock is held, so it's still consistent if the mutex was poisoned.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T> BlockingQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        BlockingQueue {
+            state: Mutex::new(State {
+                list: fifth::List::new(),
+                len: 0,
+                closed: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        lock(&self.state)
+    }
+
+    // Blocks while the queue is full. Hands the element back if the queue
+    // is closed.
+    pub fn push(&self, elem: T) -> Result<(), T> {
+        let state = self.lock();
+        let state = self
+            .not_full
+            .wait_while(state, |state| !state.closed && state.len == self.capacity)
+            .unwrap_or_else(PoisonError::into_inner);
+        self.push_locked(state, elem).map_err(|err| match err {
+            PushError::Full(elem) | PushError::Closed(elem) => elem,
+        })
+    }
+
+    pub fn try_push(&self, elem: T) -> Result<(), PushError<T>> {
+        self.push_locked(self.lock(), elem)
+    }
+
+    pub fn push_timeout(&self, elem: T, timeout: Duration) -> Result<(), PushError<T>> {
+        let state = self.lock();
+        let (state, _) = self
+            .not_full
+            .wait_timeout_while(state, timeout, |state| {
+                !state.closed && state.len == self.capacity
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        self.push_locked(state, elem)
+    }
+
+    fn push_locked(
+        &self,
+        mut state: MutexGuard<'_, State<T>>,
+        elem: T,
+    ) -> Result<(), PushError<T>> {
+        if state.closed {
+            return Err(PushError::Closed(elem));
+        }
+        if state.len == self.capacity {
+            return Err(PushError::Full(elem));
+        }
+
+        state.list.push(elem);
+        state.len += 1;
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    // Blocks while the queue is empty. Returns `None` once the queue is
+    // closed and drained.
+    pub fn pop(&self) -> Option<T> {
+        let state = self.lock();
+        let state = self
+            .not_empty
+            .wait_while(state, |state| !state.closed && state.len == 0)
+            .unwrap_or_else(PoisonError::into_inner);
+        self.pop_locked(state).ok()
+    }
+
+    pub fn try_pop(&self) -> Result<T, PopError> {
+        self.pop_locked(self.lock())
+    }
+
+    pub fn pop_timeout(&self, timeout: Duration) -> Result<T, PopError> {
+        let state = self.lock();
+        let (state, _) = self
+            .not_empty
+            .wait_timeout_while(state, timeout, |state| !state.closed && state.len == 0)
+            .unwrap_or_else(PoisonError::into_inner);
+        self.pop_locked(state)
+    }
+
+    fn pop_locked(&self, mut state: MutexGuard<'_, State<T>>) -> Result<T, PopError> {
+        match state.list.pop() {
+            Some(elem) => {
+                state.len -= 1;
+                drop(state);
+                self.not_full.notify_one();
+                Ok(elem)
+            }
+            None if state.closed => Err(PopError::Closed),
+            None => Err(PopError::Empty),
+        }
+    }
+
+    // Refuses any further pushes, wakes up every waiting thread and hands back
+    // whatever was still queued, oldest first.
+    pub fn close(&self) -> Vec<T> {
+        let mut state = self.lock();
+        state.closed = true;
+        let mut remaining = Vec::with_capacity(state.len);
+        while let Some(elem) = state.list.pop() {
+            remaining.push(elem);
+        }
+        state.len = 0;
+        drop(state);
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+        remaining
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BlockingQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("BlockingQueue")
+            .field("list", &state.list)
+            .field("capacity", &self.capacity)
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BlockingQueue, PopError, PushError};
+    use std::{
+        collections::HashSet,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn basics() {
+        let queue = BlockingQueue::new(2);
+        assert_eq!(queue.try_pop(), Err(PopError::Empty));
+        assert!(queue.is_empty());
+
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.try_push(2), Ok(()));
+        assert_eq!(queue.try_push(3), Err(PushError::Full(3)));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.capacity(), 2);
+
+        // First in, first out
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.try_push(3), Ok(()));
+        assert_eq!(
+            format!("{:?}", queue),
+            "BlockingQueue { list: [2, 3], capacity: 2, closed: false }"
+        );
+        assert_eq!(queue.try_pop(), Ok(2));
+        assert_eq!(queue.try_pop(), Ok(3));
+        assert_eq!(queue.try_pop(), Err(PopError::Empty));
+    }
+
+    #[test]
+    fn timeouts() {
+        let queue = BlockingQueue::new(1);
+        let timeout = Duration::from_millis(20);
+
+        let start = Instant::now();
+        assert_eq!(queue.pop_timeout(timeout), Err(PopError::Empty));
+        assert!(start.elapsed() >= timeout);
+
+        assert_eq!(queue.push_timeout(1, timeout), Ok(()));
+        let start = Instant::now();
+        assert_eq!(queue.push_timeout(2, timeout), Err(PushError::Full(2)));
+        assert!(start.elapsed() >= timeout);
+
+        assert_eq!(queue.pop_timeout(timeout), Ok(1));
+    }
+
+    #[test]
+    fn push_waits_for_room() {
+        let queue = Arc::new(BlockingQueue::new(1));
+        queue.push(1).unwrap();
+
+        let pusher = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(2))
+        };
+
+        // The pusher can't get its element in until we make room
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.pop(), Some(1));
+
+        assert_eq!(pusher.join().unwrap(), Ok(()));
+        assert_eq!(queue.pop(), Some(2));
+    }
+
+    #[test]
+    fn pop_waits_for_elements() {
+        let queue = Arc::new(BlockingQueue::new(1));
+
+        let popper = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.pop())
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        queue.push(1).unwrap();
+        assert_eq!(popper.join().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn close_wakes_waiters() {
+        let full = Arc::new(BlockingQueue::new(1));
+        let empty = Arc::new(BlockingQueue::<i32>::new(1));
+        full.push(1).unwrap();
+
+        let pusher = {
+            let full = full.clone();
+            thread::spawn(move || full.push(2))
+        };
+        let popper = {
+            let empty = empty.clone();
+            thread::spawn(move || empty.pop())
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(full.close(), vec![1]);
+        assert_eq!(empty.close(), vec![]);
+        assert!(full.is_closed());
+
+        // Both get out of their wait empty-handed
+        assert_eq!(pusher.join().unwrap(), Err(2));
+        assert_eq!(popper.join().unwrap(), None);
+
+        assert_eq!(full.try_push(3), Err(PushError::Closed(3)));
+        assert!(full.is_empty());
+        assert_eq!(full.pop_timeout(Duration::ZERO), Err(PopError::Closed));
+        assert_eq!(full.try_pop(), Err(PopError::Closed));
+        assert_eq!(full.pop(), None);
+    }
+
+    #[test]
+    fn close_drains() {
+        let queue = BlockingQueue::new(4);
+        for i in 1..=4 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.pop(), Some(1));
+
+        assert_eq!(queue.close(), vec![2, 3, 4]);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.try_pop(), Err(PopError::Closed));
+
+        // Closing again has nothing left to hand back
+        assert_eq!(queue.close(), vec![]);
+    }
+
+    #[test]
+    fn pipeline() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        let per_producer = if cfg!(miri) { 20 } else { 5_000 };
+
+        // A small capacity so both sides spend time blocked
+        let queue = Arc::new(BlockingQueue::new(4));
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for seq in 0..per_producer {
+                        queue.push((producer, seq)).unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    while let Some(item) = queue.pop() {
+                        seen.push(item);
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        // Consumers stop once the queue is closed, whatever they didn't get
+        // to comes back from `close`
+        let leftover = queue.close();
+
+        let mut all: HashSet<_> = leftover.into_iter().collect();
+        for consumer in consumers {
+            let seen = consumer.join().unwrap();
+
+            let mut last = [None; PRODUCERS];
+            for &(producer, seq) in &seen {
+                assert!(last[producer] < Some(seq));
+                last[producer] = Some(seq);
+            }
+
+            for item in seen {
+                assert!(all.insert(item), "{:?} popped twice", item);
+            }
+        }
+        assert_eq!(all.len(), PRODUCERS * per_producer);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,655 @@
+/*
+An async channel
+----------------
+
+Many `Sender`s and one `Receiver` share a `fifth::List` queue behind a
+`Mutex`. Sending pushes at the tail and receiving pops from the head, so
+messages from one sender come out in the order they went in.
+
+Nothing here blocks a thread. Instead of parking on a `Condvar` like
+`BlockingQueue`, a future that can't make progress leaves its `Waker` in the
+shared state and returns `Pending`:
+
+    recv on an empty channel:         receiver's waker is stored
+    send:                             pushes, wakes the receiver
+    send on a full bounded channel:   sender's waker is queued up
+    recv:                             pops, wakes the waiting senders
+
+Once every `Sender` is gone, or the receiver calls `close`, the receiver
+drains what's left and then gets `None`. Once the `Receiver` is gone, or
+closed, sends fail and hand the message back.
+
+The futures work with any executor, all they rely on is `Waker`.
+*/
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{blocking, fifth};
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // `None` for an unbounded channel
+    capacity: Option<usize>,
+}
+
+struct State<T> {
+    queue: fifth::List<T>,
+    // `fifth::List` doesn't keep track of its length
+    len: usize,
+    senders: usize,
+    // Set by `Receiver::close` and when the receiver is dropped
+    closed: bool,
+    recv_waker: Option<Waker>,
+    // Senders waiting for room in a bounded channel, one slot per waiting
+    // `SendFuture`, which takes its slot out again if it's dropped. Every
+    // receive wakes all of them, a woken `SendFuture` might be dropped
+    // before it sends and wouldn't pass a single wakeup on.
+    send_wakers: HashMap<usize, Waker>,
+    // Slot for the next `SendFuture` that has to wait
+    next_waiter: usize,
+}
+
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    channel(None)
+}
+
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be non-zero");
+    channel(Some(capacity))
+}
+
+fn channel<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: fifth::List::new(),
+            len: 0,
+            senders: 1,
+            closed: false,
+            recv_waker: None,
+            send_wakers: HashMap::new(),
+            next_waiter: 0,
+        }),
+        capacity,
+    });
+    let sender = Sender {
+        shared: shared.clone(),
+    };
+    (sender, Receiver { shared })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    // The channel is bounded and full, the message is handed back
+    Full(T),
+    Closed(T),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    // Closed and everything in it has been received
+    Closed,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        blocking::lock(&self.state)
+    }
+
+    // Pushes `elem` if there's room. If there isn't, and a `waker` is given,
+    // it's put in the waiter's slot, handing out a slot if it has none yet,
+    // to be woken once there is.
+    fn send(
+        &self,
+        elem: T,
+        waiter: Option<(&mut Option<usize>, &Waker)>,
+    ) -> Result<(), TrySendError<T>> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(TrySendError::Closed(elem));
+        }
+        if self.capacity == Some(state.len) {
+            if let Some((slot, waker)) = waiter {
+                let slot = *slot.get_or_insert_with(|| {
+                    state.next_waiter += 1;
+                    state.next_waiter
+                });
+                state.send_wakers.insert(slot, waker.clone());
+            }
+            return Err(TrySendError::Full(elem));
+        }
+
+        state.queue.push(elem);
+        state.len += 1;
+        let waker = state.recv_waker.take();
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    // Pops the next message if there is one. If there isn't, and a `waker`
+    // is given, it's stored to be woken once there is.
+    fn recv(&self, waker: Option<&Waker>) -> Result<T, TryRecvError> {
+        let mut state = self.lock();
+        match state.queue.pop() {
+            Some(elem) => {
+                state.len -= 1;
+                let wakers = mem::take(&mut state.send_wakers);
+                drop(state);
+                wakers.into_values().for_each(Waker::wake);
+                Ok(elem)
+            }
+            None if state.closed || state.senders == 0 => Err(TryRecvError::Closed),
+            None => {
+                if let Some(waker) = waker {
+                    match &mut state.recv_waker {
+                        Some(old) if old.will_wake(waker) => {}
+                        old => *old = Some(waker.clone()),
+                    }
+                }
+                Err(TryRecvError::Empty)
+            }
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Resolves once the message is in the channel, waiting for room if the
+    // channel is bounded and full.
+    pub fn send(&self, elem: T) -> SendFuture<'_, T> {
+        SendFuture {
+            sender: self,
+            elem: Some(elem),
+            slot: None,
+        }
+    }
+
+    pub fn try_send(&self, elem: T) -> Result<(), TrySendError<T>> {
+        self.shared.send(elem, None)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().closed
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // Let the receiver find out there's nothing more coming
+            let waker = state.recv_waker.take();
+            drop(state);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> Receiver<T> {
+    // Resolves to the next message, or to `None` once the channel is closed
+    // and drained.
+    pub fn recv(&mut self) -> RecvFuture<'_, T> {
+        RecvFuture { receiver: self }
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.shared.recv(None)
+    }
+
+    // Refuses any further sends and wakes up the senders waiting for room.
+    // Whatever is already in the channel can still be received.
+    pub fn close(&mut self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        let wakers = mem::take(&mut state.send_wakers);
+        drop(state);
+        wakers.into_values().for_each(Waker::wake);
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.lock().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+        // Nobody is going to receive these anymore, don't keep them around
+        // until the last sender goes
+        let queue = {
+            let mut state = self.shared.lock();
+            state.len = 0;
+            mem::take(&mut state.queue)
+        };
+        drop(queue);
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+pub struct SendFuture<'a, T> {
+    sender: &'a Sender<T>,
+    // Taken once it's been sent or handed back
+    elem: Option<T>,
+    // Where our waker goes in `send_wakers`, once we've had to wait
+    slot: Option<usize>,
+}
+
+// The message is never pinned, it's only ever moved in and out of the
+// channel.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let elem = this
+            .elem
+            .take()
+            .expect("`SendFuture` polled after completion");
+        let waiter = Some((&mut this.slot, cx.waker()));
+        match this.sender.shared.send(elem, waiter) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(TrySendError::Closed(elem)) => Poll::Ready(Err(SendError(elem))),
+            Err(TrySendError::Full(elem)) => {
+                this.elem = Some(elem);
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// An abandoned send shouldn't leave its waker behind until the next receive.
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            // Dropped after the lock is released
+            let waker = self.sender.shared.lock().send_wakers.remove(&slot);
+            drop(waker);
+        }
+    }
+}
+
+pub struct RecvFuture<'a, T> {
+    receiver: &'a mut Receiver<T>,
+}
+
+impl<T> Future for RecvFuture<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        match self.receiver.shared.recv(Some(cx.waker())) {
+            Ok(elem) => Poll::Ready(Some(elem)),
+            Err(TryRecvError::Closed) => Poll::Ready(None),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bounded, unbounded, SendError, TryRecvError, TrySendError};
+    use std::{
+        cell::RefCell,
+        collections::HashSet,
+        future::Future,
+        pin::{pin, Pin},
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        task::{Context, Poll, Wake, Waker},
+        thread::{self, Thread},
+    };
+
+    // A tiny executor: runs a future to completion on the current thread,
+    // parking it while the future is pending.
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    // And one that runs many tasks on the current thread. Waking a task puts
+    // it back on the run queue.
+    type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    struct Runnable {
+        // `None` once the task has finished
+        task: Mutex<Option<Task>>,
+        queue: mpsc::Sender<Arc<Runnable>>,
+    }
+
+    impl Wake for Runnable {
+        fn wake(self: Arc<Self>) {
+            let _ = self.queue.clone().send(self);
+        }
+    }
+
+    fn run_all(tasks: Vec<Task>) {
+        let (queue, ready) = mpsc::channel();
+        let mut pending = tasks.len();
+        for task in tasks {
+            let runnable = Arc::new(Runnable {
+                task: Mutex::new(Some(task)),
+                queue: queue.clone(),
+            });
+            queue.send(runnable).unwrap();
+        }
+
+        while pending > 0 {
+            // Everything runs on this thread, so any wakeup has already
+            // happened by now
+            let runnable = ready.try_recv().expect("every task is stuck");
+            let waker = Waker::from(runnable.clone());
+            let mut slot = runnable.task.lock().unwrap();
+            // Woken again after it already finished
+            let Some(task) = slot.as_mut() else { continue };
+            if task
+                .as_mut()
+                .poll(&mut Context::from_waker(&waker))
+                .is_ready()
+            {
+                *slot = None;
+                pending -= 1;
+            }
+        }
+    }
+
+    // Counts its wakeups, for stepping futures by hand.
+    #[derive(Default)]
+    struct CountWakes(AtomicUsize);
+
+    impl Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn unbounded_basics() {
+        let (tx, mut rx) = unbounded();
+        block_on(async {
+            tx.send(1).await.unwrap();
+            tx.send(2).await.unwrap();
+            assert_eq!(tx.try_send(3), Ok(()));
+            assert_eq!(rx.len(), 3);
+
+            assert_eq!(rx.recv().await, Some(1));
+            assert_eq!(rx.recv().await, Some(2));
+            assert_eq!(rx.try_recv(), Ok(3));
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+            // Dropping the last sender closes the channel
+            let tx2 = tx.clone();
+            drop(tx);
+            tx2.send(4).await.unwrap();
+            drop(tx2);
+            assert_eq!(rx.recv().await, Some(4));
+            assert_eq!(rx.recv().await, None);
+            assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+        });
+    }
+
+    #[test]
+    fn recv_waits_for_send() {
+        let (tx, mut rx) = unbounded();
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut recv = pin!(rx.recv());
+        assert_eq!(recv.as_mut().poll(&mut cx), Poll::Pending);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        tx.try_send(1).unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(recv.as_mut().poll(&mut cx), Poll::Ready(Some(1)));
+    }
+
+    #[test]
+    fn bounded_send_waits_for_room() {
+        let (tx, mut rx) = bounded(1);
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+
+        let mut send = pin!(tx.send(2));
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+
+        // Making room wakes the sender up
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn repolled_send_queues_one_waker() {
+        let (tx, mut rx) = bounded(1);
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        tx.try_send(1).unwrap();
+        let mut send = pin!(tx.send(2));
+        for _ in 0..100 {
+            assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+        }
+        assert_eq!(tx.shared.lock().send_wakers.len(), 1);
+
+        // Woken once, not once per poll
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn dropped_send_takes_its_waker() {
+        let (tx, mut rx) = bounded(1);
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        tx.try_send(1).unwrap();
+        for elem in 2..10 {
+            let mut send = pin!(tx.send(elem));
+            assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+            assert_eq!(tx.shared.lock().send_wakers.len(), 1);
+        }
+        assert!(tx.shared.lock().send_wakers.is_empty());
+
+        // Nobody is left to wake
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        // A send that was woken, then had to wait again, still cleans up
+        tx.try_send(1).unwrap();
+        let mut send = tx.send(2);
+        assert_eq!(Pin::new(&mut send).poll(&mut cx), Poll::Pending);
+        assert_eq!(rx.try_recv(), Ok(1));
+        tx.try_send(3).unwrap();
+        assert_eq!(Pin::new(&mut send).poll(&mut cx), Poll::Pending);
+        drop(send);
+        assert!(tx.shared.lock().send_wakers.is_empty());
+    }
+
+    #[test]
+    fn close_and_drop_receiver() {
+        let (tx, mut rx) = bounded(1);
+        let wakes = Arc::new(CountWakes::default());
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        tx.try_send(1).unwrap();
+        let mut send = pin!(tx.send(2));
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Pending);
+
+        // Closing wakes the waiting sender and hands its message back
+        rx.close();
+        assert!(tx.is_closed());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        assert_eq!(send.as_mut().poll(&mut cx), Poll::Ready(Err(SendError(2))));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Closed(3)));
+
+        // What was sent before closing is still there
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+
+        // Dropping the receiver frees whatever it never got to
+        let live = Arc::new(());
+        let (tx, rx) = unbounded();
+        tx.try_send(live.clone()).unwrap();
+        drop(rx);
+        assert_eq!(Arc::strong_count(&live), 1);
+        assert_eq!(block_on(tx.send(live.clone())).map_err(|_| ()), Err(()));
+    }
+
+    #[test]
+    fn tasks_on_one_thread() {
+        const PRODUCERS: usize = 4;
+        let per_producer = if cfg!(miri) { 20 } else { 1_000 };
+
+        // A tiny buffer so producers keep waiting on the consumer
+        let (tx, mut rx) = bounded(2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let mut tasks: Vec<Task> = Vec::new();
+        for producer in 0..PRODUCERS {
+            let tx = tx.clone();
+            tasks.push(Box::pin(async move {
+                for seq in 0..per_producer {
+                    tx.send((producer, seq)).await.unwrap();
+                }
+            }));
+        }
+        drop(tx);
+        let log = received.clone();
+        tasks.push(Box::pin(async move {
+            while let Some(msg) = rx.recv().await {
+                log.lock().unwrap().push(msg);
+            }
+        }));
+
+        run_all(tasks);
+
+        let received = received.lock().unwrap();
+        let mut last = [None; PRODUCERS];
+        for &(producer, seq) in received.iter() {
+            assert!(last[producer] < Some(seq));
+            last[producer] = Some(seq);
+        }
+        assert_eq!(received.len(), PRODUCERS * per_producer);
+    }
+
+    #[test]
+    fn producers_on_other_threads() {
+        const PRODUCERS: usize = 4;
+        let per_producer = if cfg!(miri) { 20 } else { 5_000 };
+
+        let (tx, mut rx) = bounded(8);
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    block_on(async {
+                        for seq in 0..per_producer {
+                            tx.send((producer, seq)).await.unwrap();
+                        }
+                    })
+                })
+            })
+            .collect();
+        drop(tx);
+
+        let mut all = HashSet::new();
+        block_on(async {
+            while let Some(msg) = rx.recv().await {
+                assert!(all.insert(msg), "{:?} received twice", msg);
+            }
+        });
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(all.len(), PRODUCERS * per_producer);
+    }
+
+    #[test]
+    fn works_with_non_send_messages() {
+        // Single-threaded code can pass `Rc`s around too
+        let (tx, mut rx) = unbounded();
+        let shared = Rc::new(RefCell::new(0));
+        block_on(async {
+            tx.send(shared.clone()).await.unwrap();
+            *rx.recv().await.unwrap().borrow_mut() += 1;
+        });
+        assert_eq!(*shared.borrow(), 1);
+    }
+}
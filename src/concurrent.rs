@@ -0,0 +1,32 @@
+/*
+Lock-free concurrent lists
+--------------------------
+
+Once more than one thread can reach a node, popping it is no longer the end
+of the story: another thread may have loaded a pointer to the node just
+before we unlinked it, and still be about to read through that pointer. So
+unlinked nodes are handed to `epoch`, which frees them once every thread that
+could still be looking at them has moved on.
+*/
+
+mod epoch;
+mod queue;
+mod stack;
+
+pub use queue::Queue;
+pub use stack::Stack;
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    // Logs its id when it's dropped, so a test can tell what was dropped,
+    // how many times, and in which order
+    pub(super) struct Tracked(pub(super) usize, pub(super) Arc<Mutex<Vec<usize>>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+}
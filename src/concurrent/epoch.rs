@@ -0,0 +1,398 @@
+/*
+Epoch-based reclamation
+-----------------------
+
+There's a global epoch counter, and every thread that touches a concurrent
+list first "pins" itself, recording the epoch it saw:
+
+    global epoch:  5
+    thread A:      pinned at 5
+    thread B:      pinned at 5
+    thread C:      not pinned
+
+A node that has been unlinked is tagged with the global epoch at the time and
+put into a garbage bag. The global epoch can only move from `e` to `e + 1`
+once every pinned thread has seen `e`. Any thread that could still hold a
+pointer to a node tagged `e` was pinned at `e` or earlier, so by the time the
+global epoch reaches `e + 2` all of them have unpinned, and the node can be
+freed.
+
+Each thread keeps its own bag, so retiring a node never takes a lock. When a
+thread exits, whatever is left in its bag is handed over to a shared orphan
+bag that the other threads drain.
+
+The atomic orderings follow crossbeam-epoch: pinning stores the epoch and
+then issues a `SeqCst` fence, advancing the epoch issues a `SeqCst` fence
+before scanning the participants and an `Acquire` fence after, retiring a
+node issues a `SeqCst` fence before reading the epoch to tag it with, and
+unpinning is a `Release` store.
+*/
+
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    ptr,
+    sync::{
+        atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+// How many pins a thread does between attempts to advance the epoch and
+// free its garbage.
+const COLLECT_EVERY: usize = 64;
+
+// A bag this full gets collected right away instead of waiting for a pin.
+const MAX_BAG: usize = 256;
+
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+// Every thread that has ever pinned, as a lock-free stack of participants.
+// Participants are never freed, a thread that exits gives its slot up for the
+// next new thread to reuse.
+static PARTICIPANTS: AtomicPtr<Participant> = AtomicPtr::new(ptr::null_mut());
+
+static ORPHANS: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+
+struct Participant {
+    // `epoch << 1 | 1` while pinned, `0` otherwise
+    state: AtomicUsize,
+    in_use: AtomicBool,
+    next: AtomicPtr<Participant>,
+}
+
+impl Participant {
+    fn acquire() -> &'static Participant {
+        // Reuse the slot of a thread that has exited, if there is one
+        let mut curr = PARTICIPANTS.load(Ordering::Acquire);
+        while let Some(participant) = unsafe { curr.as_ref() } {
+            if participant
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return participant;
+            }
+            curr = participant.next.load(Ordering::Acquire);
+        }
+
+        let participant: &'static Participant = Box::leak(Box::new(Participant {
+            state: AtomicUsize::new(0),
+            in_use: AtomicBool::new(true),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }));
+        let mut head = PARTICIPANTS.load(Ordering::Relaxed);
+        loop {
+            participant.next.store(head, Ordering::Relaxed);
+            match PARTICIPANTS.compare_exchange_weak(
+                head,
+                participant as *const _ as *mut _,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return participant,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+}
+
+// A node waiting to be freed, with the epoch it was retired in.
+struct Deferred {
+    ptr: *mut u8,
+    free: unsafe fn(*mut u8),
+    epoch: usize,
+}
+
+// The pointer is only ever used to free the node, see `Guard::defer_free`.
+unsafe impl Send for Deferred {}
+
+impl Deferred {
+    fn is_expired(&self, global: usize) -> bool {
+        global.wrapping_sub(self.epoch) >= 2
+    }
+
+    fn run(self) {
+        unsafe { (self.free)(self.ptr) }
+    }
+}
+
+struct Local {
+    participant: &'static Participant,
+    pin_count: Cell<usize>,
+    pins: Cell<usize>,
+    bag: RefCell<Vec<Deferred>>,
+}
+
+impl Local {
+    fn new() -> Self {
+        Local {
+            participant: Participant::acquire(),
+            pin_count: Cell::new(0),
+            pins: Cell::new(0),
+            bag: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn pin(&self) {
+        let count = self.pin_count.get();
+        self.pin_count.set(count + 1);
+        if count > 0 {
+            // Already pinned further up the stack
+            return;
+        }
+
+        let epoch = EPOCH.load(Ordering::Relaxed);
+        self.participant
+            .state
+            .store(epoch << 1 | 1, Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+
+        let pins = self.pins.get().wrapping_add(1);
+        self.pins.set(pins);
+        if pins.is_multiple_of(COLLECT_EVERY) {
+            self.collect();
+        }
+    }
+
+    fn unpin(&self) {
+        let count = self.pin_count.get() - 1;
+        self.pin_count.set(count);
+        if count == 0 {
+            self.participant.state.store(0, Ordering::Release);
+        }
+    }
+
+    fn defer(&self, deferred: Deferred) {
+        let full = {
+            let mut bag = self.bag.borrow_mut();
+            bag.push(deferred);
+            bag.len() >= MAX_BAG
+        };
+        if full {
+            self.collect();
+        }
+    }
+
+    // Frees whatever has expired in our own bag, and in the orphan bag if
+    // nobody else is busy with it.
+    fn collect(&self) {
+        let global = try_advance();
+
+        let expired: Vec<_> = {
+            let mut bag = self.bag.borrow_mut();
+            let (expired, live) = bag.drain(..).partition(|d| d.is_expired(global));
+            *bag = live;
+            expired
+        };
+        expired.into_iter().for_each(Deferred::run);
+
+        if let Ok(mut orphans) = ORPHANS.try_lock() {
+            let (expired, live): (Vec<_>, _) =
+                orphans.drain(..).partition(|d| d.is_expired(global));
+            *orphans = live;
+            drop(orphans);
+            expired.into_iter().for_each(Deferred::run);
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        let bag = std::mem::take(self.bag.get_mut());
+        ORPHANS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .extend(bag);
+
+        self.participant.state.store(0, Ordering::Release);
+        self.participant.in_use.store(false, Ordering::Release);
+    }
+}
+
+thread_local! {
+    static LOCAL: Local = Local::new();
+}
+
+// Moves the global epoch on if every pinned thread has caught up with it,
+// and returns the global epoch either way.
+fn try_advance() -> usize {
+    let global = EPOCH.load(Ordering::Relaxed);
+    fence(Ordering::SeqCst);
+
+    let mut curr = PARTICIPANTS.load(Ordering::Acquire);
+    while let Some(participant) = unsafe { curr.as_ref() } {
+        let state = participant.state.load(Ordering::Relaxed);
+        if state & 1 == 1 && state >> 1 != global {
+            return global;
+        }
+        curr = participant.next.load(Ordering::Acquire);
+    }
+    fence(Ordering::Acquire);
+
+    match EPOCH.compare_exchange(
+        global,
+        global.wrapping_add(1),
+        Ordering::Release,
+        Ordering::Relaxed,
+    ) {
+        Ok(_) => global.wrapping_add(1),
+        Err(actual) => actual,
+    }
+}
+
+// Keeps the current thread pinned for as long as it's alive. Pointers loaded
+// from a concurrent list while pinned stay valid until the guard is dropped.
+pub(crate) struct Guard {
+    // Pinning is per-thread, so the guard can't leave it
+    _not_send: PhantomData<*mut ()>,
+}
+
+pub(crate) fn pin() -> Guard {
+    LOCAL.with(Local::pin);
+    Guard {
+        _not_send: PhantomData,
+    }
+}
+
+impl Guard {
+    /// Frees `ptr` as a `Box<T>` once no thread can be looking at it anymore.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must already be unreachable for
+    /// threads that pin from now on, and must not be retired twice. Dropping
+    /// the `T` must be fine from any thread at any later point, so it can't
+    /// touch anything borrowed (nodes whose element was moved out are fine).
+    pub(crate) unsafe fn defer_free<T>(&self, ptr: *mut T) {
+        unsafe fn free<T>(ptr: *mut u8) {
+            drop(Box::from_raw(ptr as *mut T));
+        }
+
+        // Orders the unlinking before reading the epoch, the same way
+        // advancing orders its scan. Without it the tag could be an epoch
+        // that's already behind, and the node would be freed one epoch early
+        // while a thread pinned in between still holds a pointer to it.
+        fence(Ordering::SeqCst);
+        let deferred = Deferred {
+            ptr: ptr as *mut u8,
+            free: free::<T>,
+            epoch: EPOCH.load(Ordering::Relaxed),
+        };
+        LOCAL.with(|local| local.defer(deferred));
+    }
+
+    // Tries to advance the epoch and free what has expired right away.
+    #[cfg(test)]
+    pub(crate) fn flush(&self) {
+        LOCAL.with(Local::collect);
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        LOCAL.with(Local::unpin);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::pin;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+    };
+
+    // Bumps its counter when it's finally freed
+    struct Freed(Arc<AtomicUsize>);
+
+    impl Drop for Freed {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // Other tests pin in parallel and can hold the epoch back for a while,
+    // so keep flushing until the garbage goes.
+    fn flush_until(done: impl Fn() -> bool) {
+        for _ in 0..100_000 {
+            if done() {
+                return;
+            }
+            pin().flush();
+            thread::yield_now();
+        }
+        panic!("garbage was never freed");
+    }
+
+    #[test]
+    fn frees_deferred() {
+        let freed = Arc::new(AtomicUsize::new(0));
+        {
+            let guard = pin();
+            for _ in 0..10 {
+                let ptr = Box::into_raw(Box::new(Freed(freed.clone())));
+                unsafe { guard.defer_free(ptr) };
+            }
+        }
+        flush_until(|| freed.load(Ordering::SeqCst) == 10);
+    }
+
+    #[test]
+    fn waits_for_pinned_threads() {
+        let freed = Arc::new(AtomicUsize::new(0));
+        let (pinned_tx, pinned_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+
+        // Another thread pins and then sits there
+        let pinned = thread::spawn(move || {
+            let _guard = pin();
+            pinned_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        pinned_rx.recv().unwrap();
+
+        let guard = pin();
+        let ptr = Box::into_raw(Box::new(Freed(freed.clone())));
+        unsafe { guard.defer_free(ptr) };
+        drop(guard);
+
+        // The epoch can move at most one step past the pinned thread
+        for _ in 0..1_000 {
+            pin().flush();
+        }
+        assert_eq!(freed.load(Ordering::SeqCst), 0);
+
+        release_tx.send(()).unwrap();
+        pinned.join().unwrap();
+        flush_until(|| freed.load(Ordering::SeqCst) == 1);
+    }
+
+    #[test]
+    fn orphaned_garbage_is_freed() {
+        let freed = Arc::new(AtomicUsize::new(0));
+
+        let thread_freed = freed.clone();
+        thread::spawn(move || {
+            let guard = pin();
+            let ptr = Box::into_raw(Box::new(Freed(thread_freed)));
+            unsafe { guard.defer_free(ptr) };
+        })
+        .join()
+        .unwrap();
+
+        flush_until(|| freed.load(Ordering::SeqCst) == 1);
+    }
+
+    #[test]
+    fn nested_pins() {
+        let outer = pin();
+        let inner = pin();
+        drop(outer);
+        drop(inner);
+        let _again = pin();
+    }
+}
@@ -0,0 +1,318 @@
+/*
+A lock-free queue (Michael-Scott)
+---------------------------------
+
+This is `fifth::List` made concurrent: a singly-linked list with elements
+pushed at the tail and popped from the head, and a cached pointer to the
+tail. Both ends become `AtomicPtr`s updated with compare-and-swap.
+
+The list always starts with a dummy node, so `head` and `tail` never have to
+be swapped out together on an empty queue:
+
+    head -> [dummy] -> (A) -> (B) -> (C)
+                                      ^
+                                     tail
+
+Popping swings `head` over to the first real node, which becomes the new
+dummy once its element has been taken out, and retires the old dummy:
+
+    head -> [A] -> (B) -> (C)
+                          ^
+                         tail
+
+Pushing links the new node after the last one with a CAS on its `next`, then
+swings `tail` forward. Those are two separate steps, so `tail` can lag one
+node behind. Whoever notices that helps it forward before doing anything
+else.
+*/
+
+use std::{
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::epoch;
+
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    // We own `T`s through the raw pointers, let the drop checker know
+    _boo: PhantomData<T>,
+}
+
+struct Node<T> {
+    // Uninitialized in the dummy node
+    elem: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn new(elem: MaybeUninit<T>) -> *mut Self {
+        Box::into_raw(Box::new(Node {
+            elem,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+// Elements go in on one thread and come out on another, but the queue never
+// hands out `&T`, so sharing it only needs `T: Send`.
+unsafe impl<T: Send> Send for Queue<T> {}
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::new(MaybeUninit::uninit());
+        Queue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn push(&self, elem: T) {
+        let new = Node::new(MaybeUninit::new(elem));
+        let _guard = epoch::pin();
+
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            // `tail` can't have been freed, we're pinned
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+
+            if !next.is_null() {
+                // `tail` is lagging behind, help it along and retry
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            let linked = unsafe {
+                (*tail).next.compare_exchange(
+                    ptr::null_mut(),
+                    new,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+            };
+            if linked.is_ok() {
+                // If this fails someone has already helped us
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, new, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            let tail = self.tail.load(Ordering::Acquire);
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+
+            if next.is_null() {
+                return None;
+            }
+
+            if head == tail {
+                // Never let `head` overtake `tail`, or `tail` would point at
+                // a retired node
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+            {
+                unsafe {
+                    // `next` is the new dummy. We won the CAS so nobody else
+                    // reads its element, and the dummy's element is never
+                    // dropped.
+                    let elem = (*next).elem.assume_init_read();
+                    guard.defer_free(head);
+                    return Some(elem);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// We have the queue to ourselves, so nodes are freed straight away rather
+// than going through the epoch.
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let dummy = Box::from_raw(*self.head.get_mut());
+            let mut curr = dummy.next.load(Ordering::Relaxed);
+            while !curr.is_null() {
+                let mut node = Box::from_raw(curr);
+                node.elem.assume_init_drop();
+                curr = *node.next.get_mut();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{epoch, Queue};
+    use crate::concurrent::test::Tracked;
+    use std::{
+        collections::HashSet,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+    };
+
+    #[test]
+    fn basics() {
+        let queue = Queue::new();
+
+        // Check empty queue behaves right
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        // Populate queue
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert!(!queue.is_empty());
+
+        // Check normal removal
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+
+        // Push some more to make sure nothing's corrupted
+        queue.push(4);
+        queue.push(5);
+
+        // Check normal removal
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+
+        // Check exhaustion
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drop_skips_dummies() {
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+
+        // Only ever had a dummy, whose element was never initialized
+        drop(Queue::<Tracked>::new());
+
+        let queue = Queue::new();
+        for id in 0..10 {
+            queue.push(Tracked(id, dropped.clone()));
+        }
+
+        // The old dummies wait to be freed for as long as we're pinned, and
+        // the current one held `2` before it was moved out
+        let guard = epoch::pin();
+        for _ in 0..3 {
+            drop(queue.pop());
+        }
+        drop(queue);
+
+        // The popped elements went once, the rest front to back
+        assert_eq!(*dropped.lock().unwrap(), (0..10).collect::<Vec<_>>());
+
+        drop(guard);
+        for _ in 0..100 {
+            epoch::pin().flush();
+        }
+        assert_eq!(dropped.lock().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn producers_and_consumers() {
+        const PRODUCERS: usize = 4;
+        const CONSUMERS: usize = 4;
+        let per_producer = if cfg!(miri) { 50 } else { 20_000 };
+
+        let queue = Arc::new(Queue::new());
+        let popped = Arc::new(AtomicUsize::new(0));
+        let total = PRODUCERS * per_producer;
+
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let queue = queue.clone();
+                thread::spawn(move || {
+                    for seq in 0..per_producer {
+                        queue.push((producer, seq));
+                    }
+                })
+            })
+            .collect();
+
+        let consumers: Vec<_> = (0..CONSUMERS)
+            .map(|_| {
+                let queue = queue.clone();
+                let popped = popped.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    while popped.load(Ordering::SeqCst) < total {
+                        match queue.pop() {
+                            Some(item) => {
+                                popped.fetch_add(1, Ordering::SeqCst);
+                                seen.push(item);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let mut all = HashSet::new();
+        for consumer in consumers {
+            let seen = consumer.join().unwrap();
+
+            // It's a FIFO, so each consumer sees every producer's items in
+            // the order they were pushed
+            let mut last = [None; PRODUCERS];
+            for &(producer, seq) in &seen {
+                assert!(last[producer] < Some(seq));
+                last[producer] = Some(seq);
+            }
+
+            for item in seen {
+                assert!(all.insert(item), "{:?} popped twice", item);
+            }
+        }
+
+        // Every item delivered exactly once
+        assert_eq!(all.len(), total);
+        assert!(queue.is_empty());
+    }
+}
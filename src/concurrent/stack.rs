@@ -0,0 +1,307 @@
+/*
+A lock-free stack (Treiber)
+---------------------------
+
+This is `second::List` made concurrent: the same singly-linked stack, with
+the head link turned into an `AtomicPtr` that `push` and `pop` swing over
+with compare-and-swap.
+
+    head -> (C) -> (B) -> (A) -> null
+
+Popping reads `head` and its `next`, then swaps `head` from one to the other.
+Between the read and the swap, other threads may pop `C`, pop `B`, and push a
+fresh node that happens to be allocated where `C` used to be. The swap would
+then succeed and install the long gone `B` as the head. That's the ABA
+problem, and it's a use-after-free in disguise: `C`'s memory can only be
+reused once it has been freed. Popped nodes go through `epoch`, so nothing
+is freed while we're pinned and still holding a pointer to it.
+*/
+
+use std::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use super::epoch;
+
+pub struct Stack<T> {
+    head: AtomicPtr<Node<T>>,
+    // We own `T`s through the raw pointers, let the drop checker know
+    _boo: PhantomData<T>,
+}
+
+struct Node<T> {
+    // Moved out when the node is popped, the node itself is freed later
+    elem: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+// Same as `Queue`, elements only ever move between threads. The peeks take
+// `&mut self`, a shared peek would race with a `pop` dropping the element.
+unsafe impl<T: Send> Send for Stack<T> {}
+unsafe impl<T: Send> Sync for Stack<T> {}
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn push(&self, elem: T) {
+        let new = Box::into_raw(Box::new(Node {
+            elem: ManuallyDrop::new(elem),
+            next: ptr::null_mut(),
+        }));
+
+        // Nobody else can see `new` until the swap succeeds, so there's
+        // nothing to protect and no need to pin
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*new).next = head };
+            match self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            // `head` can't have been freed, we're pinned
+            let node = unsafe { head.as_ref() }?;
+            match self.head.compare_exchange_weak(
+                head,
+                node.next,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => unsafe {
+                    // We won the swap, so we're the only one taking the
+                    // element out
+                    let elem = ptr::read(&*node.elem);
+                    guard.defer_free(head);
+                    return Some(elem);
+                },
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    pub fn peek(&mut self) -> Option<&T> {
+        unsafe { self.head.get_mut().as_ref() }.map(|node| &*node.elem)
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.head.get_mut().as_mut() }.map(|node| &mut *node.elem)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        let mut curr = *self.head.get_mut();
+        while !curr.is_null() {
+            let mut node = unsafe { Box::from_raw(curr) };
+            unsafe { ManuallyDrop::drop(&mut node.elem) };
+            curr = node.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{epoch, Stack};
+    use crate::concurrent::test::Tracked;
+    use std::{
+        collections::HashSet,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    #[test]
+    fn basics() {
+        let stack = Stack::new();
+
+        // Check empty stack behaves right
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+
+        // Populate stack
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+
+        // Check normal removal
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+
+        // Push some more just to make sure nothing's corrupted
+        stack.push(4);
+        stack.push(5);
+
+        // Check normal removal
+        assert_eq!(stack.pop(), Some(5));
+        assert_eq!(stack.pop(), Some(4));
+
+        // Check exhaustion
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn peek() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.peek(), None);
+        assert_eq!(stack.peek_mut(), None);
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+
+        assert_eq!(stack.peek(), Some(&3));
+        assert_eq!(stack.peek_mut(), Some(&mut 3));
+
+        if let Some(value) = stack.peek_mut() {
+            *value = 42
+        }
+
+        assert_eq!(stack.peek(), Some(&42));
+        assert_eq!(stack.pop(), Some(42));
+    }
+
+    #[test]
+    fn drop_skips_retired_nodes() {
+        let dropped = Arc::new(Mutex::new(Vec::new()));
+        let stack = Stack::new();
+        for id in 0..10 {
+            stack.push(Tracked(id, dropped.clone()));
+        }
+
+        // Staying pinned holds the epoch back, so the nodes popped on the
+        // other thread are still waiting to be freed when the stack goes
+        let guard = epoch::pin();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..3 {
+                    drop(stack.pop());
+                }
+            });
+        });
+        drop(stack);
+
+        // The popped elements went once, the rest top to bottom
+        assert_eq!(*dropped.lock().unwrap(), (0..10).rev().collect::<Vec<_>>());
+
+        // Their elements were moved out, freeing the nodes drops nothing
+        drop(guard);
+        for _ in 0..100 {
+            epoch::pin().flush();
+        }
+        assert_eq!(dropped.lock().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn push_then_pop_everything() {
+        const THREADS: usize = 8;
+        let per_thread = if cfg!(miri) { 50 } else { 10_000 };
+
+        let stack = Arc::new(Stack::new());
+
+        let pushers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    for i in 0..per_thread {
+                        stack.push(thread * per_thread + i);
+                    }
+                })
+            })
+            .collect();
+        for pusher in pushers {
+            pusher.join().unwrap();
+        }
+
+        let poppers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    while let Some(elem) = stack.pop() {
+                        seen.push(elem);
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        let mut all = HashSet::new();
+        for popper in poppers {
+            for elem in popper.join().unwrap() {
+                assert!(all.insert(elem), "{} popped twice", elem);
+            }
+        }
+        assert_eq!(all.len(), THREADS * per_thread);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn push_and_pop_together() {
+        // Every thread pushes and pops at once, so nodes are constantly
+        // freed and reallocated under the other threads' feet. This is where
+        // ABA would show up.
+        const THREADS: usize = 8;
+        let per_thread = if cfg!(miri) { 50 } else { 10_000 };
+
+        let stack = Arc::new(Stack::new());
+
+        let workers: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let stack = stack.clone();
+                thread::spawn(move || {
+                    let mut seen = Vec::new();
+                    for i in 0..per_thread {
+                        stack.push(thread * per_thread + i);
+                        if i % 2 == 1 {
+                            seen.extend(stack.pop());
+                            seen.extend(stack.pop());
+                        }
+                    }
+                    seen
+                })
+            })
+            .collect();
+
+        let mut all = HashSet::new();
+        for worker in workers {
+            for elem in worker.join().unwrap() {
+                assert!(all.insert(elem), "{} popped twice", elem);
+            }
+        }
+        while let Some(elem) = stack.pop() {
+            assert!(all.insert(elem), "{} popped twice", elem);
+        }
+
+        // Nothing lost, nothing made up
+        assert_eq!(all.len(), THREADS * per_thread);
+        assert!(all.iter().all(|&elem| elem < THREADS * per_thread));
+    }
+}
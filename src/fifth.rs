@@ -20,14 +20,39 @@ Instead of walking the list every time the pointer to the end of the list can
 be cached. But this works better with inverted push than inverted pop.
 */
 
-use std::ptr;
+/*
+The cached tail is a raw pointer into the list, so all the links are raw
+pointers too. If `head`/`next` were `Box`es, every time we went through one
+we'd be asserting unique access to the node and invalidate the `tail` pointer
+aliasing it (this is what Miri's Stacked Borrows checks for). With raw links
+end to end, nodes are only ever turned into a `Box` again when they're freed.
+*/
 
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+/// The auto traits follow `T`:
+///
+/// ```compile_fail
+/// fn is_send<T: Send>() {}
+/// is_send::<too_many_lists::fifth::List<std::rc::Rc<i32>>>();
+/// ```
+///
+/// ```compile_fail
+/// fn is_sync<T: Sync>() {}
+/// is_sync::<too_many_lists::fifth::List<std::cell::Cell<i32>>>();
+/// ```
 pub struct List<T> {
     head: Link<T>,
-    tail: *mut Node<T>,
+    tail: Link<T>,
+    // We own `T`s through the raw pointers, let the drop checker know
+    _boo: PhantomData<T>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+type Link<T> = Option<NonNull<Node<T>>>;
 
 struct Node<T> {
     elem: T,
@@ -38,51 +63,250 @@ impl<T> List<T> {
     pub fn new() -> Self {
         List {
             head: None,
-            tail: ptr::null_mut(),
+            tail: None,
+            _boo: PhantomData,
         }
     }
 
     pub fn push(&mut self, elem: T) {
-        let mut new_tail = Box::new(Node {
-            elem: elem,
-            next: None,
-        });
+        unsafe {
+            // From here on the node is only touched through raw pointers
+            // until `pop` turns it back into a `Box`
+            let new_tail =
+                NonNull::new_unchecked(Box::into_raw(Box::new(Node { elem, next: None })));
 
-        let raw_tail: *mut _ = &mut *new_tail;
-
-        // .is_null checks for null, equivalent to checking for None
-        if !self.tail.is_null() {
-            // If the old tail existed, update it to point to the new tail
-            unsafe {
-                (*self.tail).next = Some(new_tail);
+            match self.tail {
+                // If the old tail existed, update it to point to the new tail
+                Some(old_tail) => (*old_tail.as_ptr()).next = Some(new_tail),
+                // Otherwise, update the head to point to it
+                None => self.head = Some(new_tail),
             }
-        } else {
-            // Otherwise, update the head to point to it
-            self.head = Some(new_tail);
-        }
 
-        self.tail = raw_tail;
+            self.tail = Some(new_tail);
+        }
     }
 
     pub fn pop(&mut self) -> Option<T> {
         // Grab the list's current head
-        self.head.take().map(|head| {
-            let head = *head;
+        self.head.map(|head| unsafe {
+            let head = Box::from_raw(head.as_ptr());
             self.head = head.next;
 
             // If we're out of `head`, make sure to set the tail to `None`.
             if self.head.is_none() {
-                self.tail = ptr::null_mut();
+                self.tail = None;
             }
 
             head.elem
         })
     }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.head.map(|node| unsafe { &(*node.as_ptr()).elem })
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+}
+
+/*
+Raw pointers opt us out of `Send` and `Sync`, but the queue owns its nodes
+just like a `Box` would, so it's as thread-safe as `T` is. The same goes for
+the iterators, which behave like `&T` and `&mut T`.
+
+`NonNull` (unlike `*mut`) is covariant, which keeps `List<&'static str>`
+usable where a `List<&'a str>` is wanted. `IterMut` stays invariant, like
+`&mut T`.
+*/
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+// Pops one node at a time, there's no recursive drop through the links to
+// blow the stack on long queues.
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Collecting and extending both `push`, so the queue pops elements in the
+// same order as the source.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+// Hashed like `first::List`, the elements and then the length.
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for elem in self.iter() {
+            elem.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+/// Only `Send` when `T` is `Sync`, like `&T`:
+///
+/// ```compile_fail
+/// fn is_send<T: Send>() {}
+/// is_send::<too_many_lists::fifth::Iter<'static, std::cell::Cell<i32>>>();
+/// ```
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<T> List<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.map(|node| unsafe { &*node.as_ptr() }),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.map(|node| unsafe { &*node.as_ptr() });
+            &node.elem
+        })
+    }
+}
+
+/// Invariant over `T`, like `&mut T`:
+///
+/// ```compile_fail
+/// use too_many_lists::fifth::IterMut;
+///
+/// fn iter_mut_covariant<'i, 'a, T>(x: IterMut<'i, &'static T>) -> IterMut<'i, &'a T> {
+///     x
+/// }
+/// ```
+pub struct IterMut<'a, T> {
+    next: Option<&'a mut Node<T>>,
+}
+
+impl<T> List<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head.map(|node| unsafe { &mut *node.as_ptr() }),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.take().map(|node| {
+            self.next = node.next.map(|node| unsafe { &mut *node.as_ptr() });
+            &mut node.elem
+        })
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::List;
+    use std::rc::Rc;
+
     #[test]
     fn basics() {
         let mut list = List::new();
@@ -111,4 +335,245 @@ mod test {
         assert_eq!(list.pop(), Some(5));
         assert_eq!(list.pop(), None);
     }
+
+    #[test]
+    fn into_iter() {
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn iter() {
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.next(), Some(&mut 1));
+        assert_eq!(iter.next(), Some(&mut 2));
+        assert_eq!(iter.next(), Some(&mut 3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn for_loops() {
+        let mut list = List::new();
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        for elem in &mut list {
+            *elem *= 10;
+        }
+
+        let mut seen = Vec::new();
+        for elem in &list {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![10, 20, 30]);
+
+        let mut seen = Vec::new();
+        for elem in list {
+            seen.push(elem);
+        }
+        assert_eq!(seen, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn from_iter_and_extend() {
+        let mut list: List<_> = vec![1, 2, 3].into_iter().collect();
+        list.extend(vec![4, 5]);
+
+        assert_eq!(list.pop(), Some(1));
+        list.extend(vec![6]);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn std_traits() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+
+        // The clone gets its own tail, pushing to it leaves the original alone
+        let mut copy = list.clone();
+        assert_eq!(copy, list);
+        copy.push(4);
+        assert!(list < copy);
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn peek() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        assert_eq!(list.peek(), Some(&1));
+        assert_eq!(list.peek_mut(), Some(&mut 1));
+
+        if let Some(value) = list.peek_mut() {
+            *value = 42;
+        }
+        assert_eq!(list.peek(), Some(&42));
+        assert_eq!(list.pop(), Some(42));
+        assert_eq!(list.peek(), Some(&2));
+    }
+
+    // Interleaves every kind of access to the nodes, so Miri gets to see
+    // the raw pointers and the references made from them mixed together
+    #[test]
+    fn miri_food() {
+        let mut list = List::new();
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        assert!(list.pop() == Some(1));
+        list.push(4);
+        assert!(list.pop() == Some(2));
+        list.push(5);
+
+        assert!(list.peek() == Some(&3));
+        list.push(6);
+        if let Some(x) = list.peek_mut() {
+            *x *= 10;
+        }
+        assert!(list.peek() == Some(&30));
+        assert!(list.pop() == Some(30));
+
+        for elem in list.iter_mut() {
+            *elem *= 100;
+        }
+
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&400));
+        assert_eq!(iter.next(), Some(&500));
+        assert_eq!(iter.next(), Some(&600));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+
+        assert!(list.pop() == Some(400));
+        if let Some(x) = list.peek_mut() {
+            *x *= 10;
+        }
+        assert!(list.peek() == Some(&5000));
+        list.push(7);
+
+        // Drop it on the ground and let the dtor exercise itself
+    }
+
+    #[test]
+    fn drop_frees_elements() {
+        // Every element is a clone of `live`, its strong count tracks how
+        // many are still around
+        let live = Rc::new(());
+        let mut list = List::new();
+        for _ in 0..10 {
+            list.push(live.clone());
+        }
+        list.pop();
+        assert_eq!(Rc::strong_count(&live), 10);
+
+        drop(list);
+        assert_eq!(Rc::strong_count(&live), 1);
+    }
+
+    #[test]
+    fn long_list() {
+        // Dropping must not recurse once per node. Miri is slow, so it gets
+        // a shorter queue.
+        let len = if cfg!(miri) { 1_000 } else { 100_000 };
+        let list: List<_> = (0..len).collect();
+        drop(list);
+    }
+
+    #[test]
+    fn send_sync_and_variance() {
+        use super::{IntoIter, Iter, IterMut};
+        use std::cell::Cell;
+
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32>>();
+        is_sync::<List<i32>>();
+        is_send::<IntoIter<i32>>();
+        is_sync::<IntoIter<i32>>();
+        is_send::<Iter<i32>>();
+        is_sync::<Iter<i32>>();
+        is_send::<IterMut<i32>>();
+        is_sync::<IterMut<i32>>();
+
+        // `Cell` is `Send` but not `Sync`, so it can move with the queue
+        // or an `IterMut` but not be shared
+        is_send::<List<Cell<i32>>>();
+        is_send::<IterMut<Cell<i32>>>();
+
+        fn list_covariant<'a, T: ?Sized>(x: List<&'static T>) -> List<&'a T> {
+            x
+        }
+        fn into_iter_covariant<'a, T: ?Sized>(x: IntoIter<&'static T>) -> IntoIter<&'a T> {
+            x
+        }
+        fn iter_covariant<'i, 'a, T: ?Sized>(x: Iter<'i, &'static T>) -> Iter<'i, &'a T> {
+            x
+        }
+
+        let list: List<&'static str> = vec!["a", "b"].into_iter().collect();
+        assert_eq!(iter_covariant(list.iter()).count(), 2);
+        assert_eq!(into_iter_covariant(list.clone().into_iter()).count(), 2);
+
+        // A queue of `'static` strings takes shorter-lived ones once it's
+        // been shortened to their lifetime
+        let local = String::from("c");
+        let mut shorter = list_covariant(list);
+        shorter.push(&local);
+        assert_eq!(shorter.into_iter().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn move_to_another_thread() {
+        let list: List<_> = (0..100).collect();
+
+        let sum = std::thread::spawn(move || list.into_iter().sum::<i32>())
+            .join()
+            .unwrap();
+        assert_eq!(sum, 4950);
+
+        // Shared between threads through a plain reference
+        let list: List<_> = (0..100).collect();
+        std::thread::scope(|scope| {
+            let evens = scope.spawn(|| list.iter().filter(|x| *x % 2 == 0).count());
+            let odds = scope.spawn(|| list.iter().filter(|x| *x % 2 == 1).count());
+            assert_eq!(evens.join().unwrap() + odds.join().unwrap(), 100);
+        });
+    }
 }
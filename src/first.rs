@@ -1,34 +1,55 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::mem;
 
-pub struct List {
-    head: Link,
+pub struct List<T> {
+    head: Link<T>,
 }
 
-enum Link {
+enum Link<T> {
     Empty,
-    More(Box<Node>),
+    More(Box<Node<T>>),
 }
 
-struct Node {
-    elem: i32,
-    next: Link,
+struct Node<T> {
+    elem: T,
+    next: Link<T>,
 }
 
-impl List {
+impl<T> Link<T> {
+    // `Link` is our own enum rather than an `Option`, so we spell out the
+    // `as_deref`/`as_deref_mut` conversions the iterators need.
+    fn as_deref(&self) -> Option<&Node<T>> {
+        match self {
+            Link::Empty => None,
+            Link::More(node) => Some(node),
+        }
+    }
+
+    fn as_deref_mut(&mut self) -> Option<&mut Node<T>> {
+        match self {
+            Link::Empty => None,
+            Link::More(node) => Some(node),
+        }
+    }
+}
+
+impl<T> List<T> {
     pub fn new() -> Self {
         List { head: Link::Empty }
     }
 
-    pub fn push(&mut self, elem: i32) {
+    pub fn push(&mut self, elem: T) {
         let new_node = Box::new(Node {
-            elem: elem,
+            elem,
             next: mem::replace(&mut self.head, Link::Empty),
         });
 
         self.head = Link::More(new_node);
     }
 
-    pub fn pop(&mut self) -> Option<i32> {
+    pub fn pop(&mut self) -> Option<T> {
         match mem::replace(&mut self.head, Link::Empty) {
             Link::Empty => None,
             Link::More(node) => {
@@ -37,10 +58,25 @@ impl List {
             }
         }
     }
-}
 
-impl Drop for List {
-    fn drop(&mut self) {
+    pub fn peek(&self) -> Option<&T> {
+        self.head.as_deref().map(|node| &node.elem)
+    }
+
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.head.as_deref_mut().map(|node| &mut node.elem)
+    }
+
+    // Walks the whole stack, there is no cached length.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.head, Link::Empty)
+    }
+
+    pub fn clear(&mut self) {
         let mut curr_link = mem::replace(&mut self.head, Link::Empty);
         while let Link::More(mut boxed_node) = curr_link {
             curr_link = mem::replace(&mut boxed_node.next, Link::Empty)
@@ -51,6 +87,170 @@ impl Drop for List {
     }
 }
 
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Collecting keeps the iterator's order, so `iter().collect()` round-trips
+// and the first element ends up on top of the stack.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // A stack is built from the bottom up, so push the elements in reverse
+        let elems: Vec<_> = iter.into_iter().collect();
+        let mut list = List::new();
+        for elem in elems.into_iter().rev() {
+            list.push(elem);
+        }
+        list
+    }
+}
+
+// Extending behaves like a `push` loop, the last element ends up on top.
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+// The length goes in after the elements since we only learn it by walking
+// the list, it keeps nested lists like `([1, 2], [3])` and `([1], [2, 3])`
+// from feeding the hasher the same stream.
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for elem in self.iter() {
+            elem.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
+    }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+impl<T> List<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            &node.elem
+        })
+    }
+}
+
+pub struct IterMut<'a, T> {
+    next: Option<&'a mut Node<T>>,
+}
+
+impl<T> List<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head.as_deref_mut(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.take().map(|node| {
+            self.next = node.next.as_deref_mut();
+            &mut node.elem
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::List;
@@ -82,4 +282,194 @@ mod test {
         // Check exhaustion
         assert_eq!(list.pop(), Some(1));
     }
+
+    #[test]
+    fn generic() {
+        let mut list = List::new();
+        list.push(String::from("a"));
+        list.push(String::from("b"));
+
+        assert_eq!(list.pop().as_deref(), Some("b"));
+        assert_eq!(list.pop().as_deref(), Some("a"));
+        assert_eq!(list.pop(), None);
+    }
+
+    #[test]
+    fn peek() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        assert_eq!(list.peek(), Some(&3));
+        assert_eq!(list.peek_mut(), Some(&mut 3));
+
+        if let Some(value) = list.peek_mut() {
+            *value = 42;
+        }
+        assert_eq!(list.peek(), Some(&42));
+        assert_eq!(list.pop(), Some(42));
+    }
+
+    #[test]
+    fn len_and_clear() {
+        let mut list = List::new();
+        assert_eq!(list.len(), 0);
+        assert!(list.is_empty());
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+        assert_eq!(list.len(), 3);
+        assert!(!list.is_empty());
+
+        list.clear();
+        assert_eq!(list.len(), 0);
+        assert!(list.is_empty());
+        assert_eq!(list.pop(), None);
+
+        // Still usable after being cleared
+        list.push(4);
+        assert_eq!(list.peek(), Some(&4));
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut list = List::new();
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn iter() {
+        let mut list = List::new();
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut list = List::new();
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.next(), Some(&mut 3));
+        assert_eq!(iter.next(), Some(&mut 2));
+        assert_eq!(iter.next(), Some(&mut 1));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn long_list() {
+        // Dropping must not recurse once per node
+        let mut list = List::new();
+        for i in 0..100_000 {
+            list.push(i);
+        }
+        assert_eq!(list.len(), 100_000);
+    }
+
+    #[test]
+    fn for_loops() {
+        let mut list = List::new();
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        for elem in &mut list {
+            *elem *= 10;
+        }
+
+        let mut seen = Vec::new();
+        for elem in &list {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![30, 20, 10]);
+
+        let mut seen = Vec::new();
+        for elem in list {
+            seen.push(elem);
+        }
+        assert_eq!(seen, vec![30, 20, 10]);
+    }
+
+    #[test]
+    fn from_iter() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(list.peek(), Some(&1));
+
+        let empty: List<i32> = std::iter::empty().collect();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn extend() {
+        let mut list: List<_> = vec![1, 2].into_iter().collect();
+        list.extend(vec![3, 4]);
+
+        assert_eq!(list.pop(), Some(4));
+        assert_eq!(list.pop(), Some(3));
+        assert_eq!(list.pop(), Some(1));
+        assert_eq!(list.pop(), Some(2));
+        assert_eq!(list.pop(), None);
+    }
+
+    #[test]
+    fn std_traits() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash<T: Hash>(value: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(list, copy);
+        assert_eq!(hash(&list), hash(&copy));
+
+        let longer: List<_> = vec![1, 2, 3, 4].into_iter().collect();
+        let bigger: List<_> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, longer);
+        assert!(list < longer);
+        assert!(list < bigger);
+        assert_eq!(longer.cmp(&bigger), std::cmp::Ordering::Less);
+        assert_eq!(List::<i32>::new(), List::default());
+
+        // Without the length these would hash the same elements in a row
+        let split = |at: usize| -> (List<i32>, List<i32>) {
+            let (a, b) = [1, 2, 3].split_at(at);
+            (a.iter().copied().collect(), b.iter().copied().collect())
+        };
+        assert_ne!(hash(&split(1)), hash(&split(2)));
+    }
 }
@@ -1,25 +1,51 @@
+/*
+A doubly-linked deque
+---------------------
+
+Nodes are owned front to back. `head` and every `next` link are strong, while
+the `prev` links only point back with a `Weak`:
+
+    head ==> A ==> B ==> C <== tail
+             A <-- B <-- C
+
+    ==> strong `Rc`
+    --> `Weak`
+
+So there are no `Rc` cycles. A node is only kept alive by whatever is in
+front of it (plus `tail` for the last one), and if a link is ever left behind
+the chain behind it still gets freed.
+*/
+
 use std::{
     cell::{Ref, RefCell, RefMut},
-    rc::Rc,
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    mem, ptr,
+    rc::{Rc, Weak},
 };
 
 pub struct List<T> {
     head: Link<T>,
     tail: Link<T>,
+    // What the `NodeHandle`s this list made point back to, so they can be
+    // told apart from everyone else's. Only made once there's a handle.
+    owner: Option<Rc<()>>,
 }
 
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
+type WeakLink<T> = Option<Weak<RefCell<Node<T>>>>;
 
 struct Node<T> {
     elem: T,
-    prev: Link<T>,
+    prev: WeakLink<T>,
     next: Link<T>,
 }
 
 impl<T> Node<T> {
     fn new(elem: T) -> Rc<RefCell<Self>> {
         Rc::new(RefCell::new(Node {
-            elem: elem,
+            elem,
             prev: None,
             next: None,
         }))
@@ -31,20 +57,22 @@ impl<T> List<T> {
         List {
             head: None,
             tail: None,
+            owner: None,
         }
     }
 
     pub fn push_front(&mut self, elem: T) {
-        // new node needs +2 links, everything else should be +0
+        // new node needs +1 strong link (+2 if it's also the tail),
+        // everything else should be +0
         let new_head = Node::new(elem);
         match self.head.take() {
             Some(old_head) => {
                 // non-empty list, need to connect to old_head
-                old_head.borrow_mut().prev = Some(new_head.clone()); // +1 new head
+                old_head.borrow_mut().prev = Some(Rc::downgrade(&new_head)); // weak, +0
                 new_head.borrow_mut().next = Some(old_head); // +1 old_head
                 self.head = Some(new_head); // +1 new head, -1 old head
 
-                // total: +2 new_head, +0 old_head -- OK!
+                // total: +1 new_head, +0 old_head -- OK!
             }
             None => {
                 // empty list, need to set the tail
@@ -60,8 +88,8 @@ impl<T> List<T> {
         let new_tail = Node::new(elem);
         match self.tail.take() {
             Some(old_tail) => {
+                new_tail.borrow_mut().prev = Some(Rc::downgrade(&old_tail));
                 old_tail.borrow_mut().next = Some(new_tail.clone());
-                new_tail.borrow_mut().prev = Some(old_tail);
                 self.tail = Some(new_tail);
             }
             None => {
@@ -73,7 +101,7 @@ impl<T> List<T> {
     }
 
     pub fn pop_front(&mut self) -> Option<T> {
-        // need to take the old head, ensuring it's -2
+        // need to take the old head, ensuring it has no strong links left
         self.head.take().map(|old_head| {
             match old_head.borrow_mut().next.take() {
                 Some(new_head) => {
@@ -92,7 +120,10 @@ impl<T> List<T> {
 
     pub fn pop_back(&mut self) -> Option<T> {
         self.tail.take().map(|old_tail| {
-            match old_tail.borrow_mut().prev.take() {
+            // The node in front of the tail is alive as long as the list is,
+            // it holds the tail's strong `next` link
+            let prev = old_tail.borrow_mut().prev.take();
+            match prev.and_then(|prev| prev.upgrade()) {
                 Some(new_tail) => {
                     // not emptying list
                     new_tail.borrow_mut().next.take();
@@ -107,41 +138,147 @@ impl<T> List<T> {
         })
     }
 
-    pub fn peek_front(&self) -> Option<Ref<T>> {
+    pub fn peek_front(&self) -> Option<Ref<'_, T>> {
         self.head
             .as_ref()
             .map(|node| Ref::map(node.borrow(), |node| &node.elem))
     }
 
-    pub fn peek_front_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.head
             .as_ref()
             .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
     }
 
-    pub fn peek_back(&self) -> Option<Ref<T>> {
+    pub fn peek_back(&self) -> Option<Ref<'_, T>> {
         self.tail
             .as_ref()
             .map(|node| Ref::map(node.borrow(), |node| &node.elem))
     }
 
-    pub fn peek_back_mut(&mut self) -> Option<RefMut<T>> {
+    pub fn peek_back_mut(&mut self) -> Option<RefMut<'_, T>> {
         self.tail
             .as_ref()
             .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
     }
 }
 
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Collecting and extending both `push_back`, so the deque iterates front to
+// back in the same order as the source.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push_back(elem);
+        }
+    }
+}
+
+// Unlinks front to back so dropping a long list doesn't recurse once per
+// node. Nothing needs unwrapping here, so this doesn't rely on the nodes
+// having no other strong references.
 impl<T> Drop for List<T> {
     fn drop(&mut self) {
-        while self.pop_front().is_some() {}
+        self.tail.take();
+        let mut head = self.head.take();
+        while let Some(node) = head {
+            head = node.borrow_mut().next.take();
+        }
+    }
+}
+
+// A deep clone, every node is rebuilt through `push_back` so the clone gets
+// its own `prev`/`next` links and shares nothing with the original.
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().map(|elem| elem.clone()).collect()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// `Ref` doesn't implement the comparison traits, so the elements are wrapped
+// in something that compares what it points at.
+struct ByElem<'a, T>(Ref<'a, T>);
+
+impl<T: PartialEq> PartialEq for ByElem<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        *self.0 == *other.0
+    }
+}
+
+impl<T: Eq> Eq for ByElem<'_, T> {}
+
+impl<T: PartialOrd> PartialOrd for ByElem<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (*self.0).partial_cmp(&*other.0)
+    }
+}
+
+impl<T: Ord> Ord for ByElem<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (*self.0).cmp(&*other.0)
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().map(ByElem).eq(other.iter().map(ByElem))
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter()
+            .map(ByElem)
+            .partial_cmp(other.iter().map(ByElem))
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().map(ByElem).cmp(other.iter().map(ByElem))
+    }
+}
+
+// Hashed like `first::List`, the elements and then the length.
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for elem in self.iter() {
+            elem.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
     }
 }
 
 pub struct IntoIter<T>(List<T>);
 
-impl<T> List<T> {
-    pub fn into_iter(self) -> IntoIter<T> {
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
         IntoIter(self)
     }
 }
@@ -160,9 +297,543 @@ impl<T> DoubleEndedIterator for IntoIter<T> {
     }
 }
 
+// Every node is owned by a strong `Rc` held by the list (through `head` or a
+// `next` link), and links are only rewritten through `&mut List`. So while
+// the list is borrowed for `'a`, each node reachable from it stays put for
+// all of `'a` and we can hand out `&'a RefCell<Node<T>>` to it. Elements are
+// then borrowed through the `RefCell` like everywhere else.
+fn node_ref<'a, T>(link: &Link<T>) -> Option<&'a RefCell<Node<T>>> {
+    link.as_ref().map(|node| unsafe { &*Rc::as_ptr(node) })
+}
+
+// Same as `node_ref` for the `Weak` back links. The node in front holds the
+// strong link, so the pointer is just as good.
+fn weak_node_ref<'a, T>(link: &WeakLink<T>) -> Option<&'a RefCell<Node<T>>> {
+    link.as_ref().map(|node| unsafe { &*node.as_ptr() })
+}
+
+pub struct Iter<'a, T> {
+    front: Option<&'a RefCell<Node<T>>>,
+    back: Option<&'a RefCell<Node<T>>>,
+}
+
+impl<T> List<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.head.as_deref(),
+            back: self.tail.as_deref(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = Ref<'a, T>;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = Ref<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.front.map(|node| {
+            // Once the ends meet we're out of nodes
+            if ptr::eq(node, self.back.unwrap()) {
+                self.front = None;
+                self.back = None;
+            } else {
+                self.front = node_ref(&node.borrow().next);
+            }
+            Ref::map(node.borrow(), |node| &node.elem)
+        })
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.back.map(|node| {
+            if ptr::eq(node, self.front.unwrap()) {
+                self.front = None;
+                self.back = None;
+            } else {
+                self.back = weak_node_ref(&node.borrow().prev);
+            }
+            Ref::map(node.borrow(), |node| &node.elem)
+        })
+    }
+}
+
+// The links are read before handing out the `RefMut` for a node, and the
+// iterator never borrows that node again, so callers may hold on to as many
+// of the yielded `RefMut`s as they like.
+pub struct IterMut<'a, T> {
+    front: Option<&'a RefCell<Node<T>>>,
+    back: Option<&'a RefCell<Node<T>>>,
+}
+
+impl<T> List<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            front: self.head.as_deref(),
+            back: self.tail.as_deref(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = RefMut<'a, T>;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = RefMut<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.front.map(|node| {
+            if ptr::eq(node, self.back.unwrap()) {
+                self.front = None;
+                self.back = None;
+            } else {
+                self.front = node_ref(&node.borrow().next);
+            }
+            RefMut::map(node.borrow_mut(), |node| &mut node.elem)
+        })
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.back.map(|node| {
+            if ptr::eq(node, self.front.unwrap()) {
+                self.front = None;
+                self.back = None;
+            } else {
+                self.back = weak_node_ref(&node.borrow().prev);
+            }
+            RefMut::map(node.borrow_mut(), |node| &mut node.elem)
+        })
+    }
+}
+
+/*
+Cursors work like the ones on `std::collections::LinkedList`. A cursor sits on
+a node or on the "ghost" position, which isn't an element and lives between
+the tail and the head, so moving keeps going round in a circle:
+
+    ghost -> head -> ... -> tail -> ghost -> head -> ...
+
+At the ghost, "next" is the head and "prev" is the tail. That makes
+`insert_after` at the ghost a `push_front` and `insert_before` a `push_back`.
+*/
+pub struct Cursor<'a, T> {
+    list: &'a List<T>,
+    // `None` is the ghost
+    current: Option<&'a RefCell<Node<T>>>,
+}
+
+impl<T> List<T> {
+    pub fn cursor_front(&self) -> Cursor<'_, T> {
+        Cursor {
+            list: self,
+            current: self.head.as_deref(),
+        }
+    }
+
+    pub fn cursor_back(&self) -> Cursor<'_, T> {
+        Cursor {
+            list: self,
+            current: self.tail.as_deref(),
+        }
+    }
+}
+
+impl<'a, T> Cursor<'a, T> {
+    fn next_node(&self) -> Option<&'a RefCell<Node<T>>> {
+        match self.current {
+            Some(node) => node_ref(&node.borrow().next),
+            None => self.list.head.as_deref(),
+        }
+    }
+
+    fn prev_node(&self) -> Option<&'a RefCell<Node<T>>> {
+        match self.current {
+            Some(node) => weak_node_ref(&node.borrow().prev),
+            None => self.list.tail.as_deref(),
+        }
+    }
+
+    pub fn current(&self) -> Option<Ref<'a, T>> {
+        self.current
+            .map(|node| Ref::map(node.borrow(), |node| &node.elem))
+    }
+
+    pub fn peek_next(&self) -> Option<Ref<'a, T>> {
+        self.next_node()
+            .map(|node| Ref::map(node.borrow(), |node| &node.elem))
+    }
+
+    pub fn peek_prev(&self) -> Option<Ref<'a, T>> {
+        self.prev_node()
+            .map(|node| Ref::map(node.borrow(), |node| &node.elem))
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = self.next_node();
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = self.prev_node();
+    }
+}
+
+// The cursor only keeps a `Weak` to its node. A strong one would outlive the
+// borrow of the list if the cursor is left lying around, and trip up the
+// `Rc::try_unwrap` when that node is popped later on.
+pub struct CursorMut<'a, T> {
+    list: &'a mut List<T>,
+    // `None` is the ghost
+    current: Option<Weak<RefCell<Node<T>>>>,
+}
+
+impl<T> List<T> {
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        let current = self.head.as_ref().map(Rc::downgrade);
+        CursorMut {
+            list: self,
+            current,
+        }
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        let current = self.tail.as_ref().map(Rc::downgrade);
+        CursorMut {
+            list: self,
+            current,
+        }
+    }
+
+    // Links the chain `first ..= last` in between `prev` and `next`, which
+    // must be neighbours. `None` stands in for the ends of the list, so
+    // linking in between `None` and `None` fills an empty list.
+    fn link_between(
+        &mut self,
+        prev: Link<T>,
+        next: Link<T>,
+        first: Rc<RefCell<Node<T>>>,
+        last: Rc<RefCell<Node<T>>>,
+    ) {
+        match &prev {
+            Some(prev) => prev.borrow_mut().next = Some(first.clone()),
+            None => self.head = Some(first.clone()),
+        }
+        first.borrow_mut().prev = prev.as_ref().map(Rc::downgrade);
+
+        match &next {
+            Some(next) => next.borrow_mut().prev = Some(Rc::downgrade(&last)),
+            None => self.tail = Some(last.clone()),
+        }
+        last.borrow_mut().next = next;
+    }
+
+    // Cuts `node`, which must be in this list, out of it and hands back what
+    // used to follow it.
+    fn unlink(&mut self, node: &Rc<RefCell<Node<T>>>) -> Link<T> {
+        let (prev, next) = {
+            let node = node.borrow();
+            (
+                node.prev.as_ref().and_then(Weak::upgrade),
+                node.next.clone(),
+            )
+        };
+
+        node.borrow_mut().prev = None;
+        node.borrow_mut().next = None;
+        match &prev {
+            Some(prev) => prev.borrow_mut().next = next.clone(),
+            None => self.head = next.clone(),
+        }
+        match &next {
+            Some(next) => next.borrow_mut().prev = prev.as_ref().map(Rc::downgrade),
+            None => self.tail = prev,
+        }
+        next
+    }
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    // The list owns every node we can reach, so upgrading only fails if the
+    // cursor is on the ghost.
+    fn current_node(&self) -> Link<T> {
+        self.current.as_ref().and_then(Weak::upgrade)
+    }
+
+    fn next_node(&self) -> Link<T> {
+        self.current_node()
+            .map_or_else(|| self.list.head.clone(), |node| node.borrow().next.clone())
+    }
+
+    fn prev_node(&self) -> Link<T> {
+        self.current_node().map_or_else(
+            || self.list.tail.clone(),
+            |node| node.borrow().prev.as_ref().and_then(Weak::upgrade),
+        )
+    }
+
+    pub fn current(&mut self) -> Option<RefMut<'_, T>> {
+        node_ref(&self.current_node())
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
+    }
+
+    pub fn peek_next(&mut self) -> Option<RefMut<'_, T>> {
+        node_ref(&self.next_node())
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
+    }
+
+    pub fn peek_prev(&mut self) -> Option<RefMut<'_, T>> {
+        node_ref(&self.prev_node())
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
+    }
+
+    pub fn move_next(&mut self) {
+        self.current = self.next_node().as_ref().map(Rc::downgrade);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.current = self.prev_node().as_ref().map(Rc::downgrade);
+    }
+
+    pub fn insert_after(&mut self, elem: T) {
+        let new = Node::new(elem);
+        let (prev, next) = (self.current_node(), self.next_node());
+        self.list.link_between(prev, next, new.clone(), new);
+    }
+
+    pub fn insert_before(&mut self, elem: T) {
+        let new = Node::new(elem);
+        let (prev, next) = (self.prev_node(), self.current_node());
+        self.list.link_between(prev, next, new.clone(), new);
+    }
+
+    // Unlinks the current node and moves the cursor on to the next one.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let node = self.current.take().and_then(|node| node.upgrade())?;
+        let next = self.list.unlink(&node);
+        self.current = next.as_ref().map(Rc::downgrade);
+
+        Some(Rc::try_unwrap(node).ok().unwrap().into_inner().elem)
+    }
+
+    // Moves everything after the cursor out into a new list. At the ghost
+    // that's the whole list.
+    pub fn split_after(&mut self) -> List<T> {
+        let node = match self.current_node() {
+            Some(node) => node,
+            None => return mem::take(self.list),
+        };
+        let next = node.borrow_mut().next.take();
+        match next {
+            Some(next) => {
+                next.borrow_mut().prev = None;
+                let tail = self.list.tail.replace(node);
+                // Handles to the nodes that moved must stop working here
+                self.list.owner = None;
+                List {
+                    head: Some(next),
+                    tail,
+                    owner: None,
+                }
+            }
+            None => List::new(),
+        }
+    }
+
+    // Moves everything before the cursor out into a new list. At the ghost
+    // that's the whole list.
+    pub fn split_before(&mut self) -> List<T> {
+        let node = match self.current_node() {
+            Some(node) => node,
+            None => return mem::take(self.list),
+        };
+        let prev = node.borrow_mut().prev.take();
+        match prev.and_then(|prev| prev.upgrade()) {
+            Some(prev) => {
+                prev.borrow_mut().next = None;
+                let head = self.list.head.replace(node);
+                self.list.owner = None;
+                List {
+                    head,
+                    tail: Some(prev),
+                    owner: None,
+                }
+            }
+            None => List::new(),
+        }
+    }
+
+    // Links all of `list` in after the cursor, at the ghost it's prepended.
+    pub fn splice_after(&mut self, mut list: List<T>) {
+        if let (Some(first), Some(last)) = (list.head.take(), list.tail.take()) {
+            let (prev, next) = (self.current_node(), self.next_node());
+            self.list.link_between(prev, next, first, last);
+        }
+    }
+
+    // Links all of `list` in before the cursor, at the ghost it's appended.
+    pub fn splice_before(&mut self, mut list: List<T>) {
+        if let (Some(first), Some(last)) = (list.head.take(), list.tail.take()) {
+            let (prev, next) = (self.prev_node(), self.current_node());
+            self.list.link_between(prev, next, first, last);
+        }
+    }
+}
+
+/*
+Node handles
+------------
+
+Pushing through `push_front_handle`/`push_back_handle` hands back a
+`NodeHandle` to the new node, which can later be used to remove it, move it
+to the front, or insert next to it in O(1).
+
+A handle is a `Weak` to the node so it never keeps the node alive. Once the
+element has been popped, removed, or the list dropped the handle goes stale,
+and operations on it report that rather than doing anything.
+
+A handle also holds a `Weak` to an owner token of the list that made it, and
+only works with that list. Any other list treats it like a stale handle,
+otherwise it could unlink a node out from under that list's iterator or
+cursor. Splitting a list drops its token, because some of its nodes now live
+in another list and there's no telling which in O(1), so every handle into
+it goes stale then, including the ones to nodes that stayed. So do the
+handles into a list that gets spliced into another one, their token goes
+with the spliced list.
+*/
+pub struct NodeHandle<T> {
+    node: Weak<RefCell<Node<T>>>,
+    owner: Weak<()>,
+}
+
+impl<T> NodeHandle<T> {
+    pub fn is_stale(&self) -> bool {
+        self.node.strong_count() == 0 || self.owner.strong_count() == 0
+    }
+}
+
+impl<T> Clone for NodeHandle<T> {
+    fn clone(&self) -> Self {
+        NodeHandle {
+            node: self.node.clone(),
+            owner: self.owner.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for NodeHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeHandle")
+            .field("stale", &self.is_stale())
+            .finish()
+    }
+}
+
+impl<T> List<T> {
+    fn handle_to(&mut self, node: &Rc<RefCell<Node<T>>>) -> NodeHandle<T> {
+        let owner = self.owner.get_or_insert_with(|| Rc::new(()));
+        NodeHandle {
+            node: Rc::downgrade(node),
+            owner: Rc::downgrade(owner),
+        }
+    }
+
+    // The node behind `handle`, if it's still alive and the handle is from
+    // this list, which means the node is in it. The handle's `Weak` keeps
+    // the token's allocation around, so its address can't be reused by
+    // another list's token.
+    fn handle_node(&self, handle: &NodeHandle<T>) -> Link<T> {
+        let owner = self.owner.as_ref()?;
+        if !ptr::eq(handle.owner.as_ptr(), Rc::as_ptr(owner)) {
+            return None;
+        }
+        handle.node.upgrade()
+    }
+
+    pub fn push_front_handle(&mut self, elem: T) -> NodeHandle<T> {
+        let new = Node::new(elem);
+        let handle = self.handle_to(&new);
+        let next = self.head.clone();
+        self.link_between(None, next, new.clone(), new);
+        handle
+    }
+
+    pub fn push_back_handle(&mut self, elem: T) -> NodeHandle<T> {
+        let new = Node::new(elem);
+        let handle = self.handle_to(&new);
+        let prev = self.tail.clone();
+        self.link_between(prev, None, new.clone(), new);
+        handle
+    }
+
+    // Returns `None` if the handle is stale or from another list.
+    pub fn remove(&mut self, handle: &NodeHandle<T>) -> Option<T> {
+        let node = self.handle_node(handle)?;
+        self.unlink(&node);
+        Some(Rc::try_unwrap(node).ok().unwrap().into_inner().elem)
+    }
+
+    // Returns `false` if the handle is stale or from another list.
+    pub fn move_to_front(&mut self, handle: &NodeHandle<T>) -> bool {
+        let node = match self.handle_node(handle) {
+            Some(node) => node,
+            None => return false,
+        };
+        self.unlink(&node);
+        let next = self.head.clone();
+        self.link_between(None, next, node.clone(), node);
+        true
+    }
+
+    // Hands `elem` back if the handle is stale or from another list.
+    pub fn insert_before(&mut self, handle: &NodeHandle<T>, elem: T) -> Result<NodeHandle<T>, T> {
+        let node = match self.handle_node(handle) {
+            Some(node) => node,
+            None => return Err(elem),
+        };
+        let prev = node.borrow().prev.as_ref().and_then(Weak::upgrade);
+        let new = Node::new(elem);
+        let new_handle = self.handle_to(&new);
+        self.link_between(prev, Some(node), new.clone(), new);
+        Ok(new_handle)
+    }
+
+    // Borrows the element behind `handle`, or returns `None` if it's stale
+    // or from another list.
+    // The node is in this list, so it stays put as long as the list is
+    // borrowed, like for `node_ref`.
+    pub fn get(&self, handle: &NodeHandle<T>) -> Option<Ref<'_, T>> {
+        node_ref(&self.handle_node(handle)).map(|node| Ref::map(node.borrow(), |node| &node.elem))
+    }
+
+    pub fn get_mut(&mut self, handle: &NodeHandle<T>) -> Option<RefMut<'_, T>> {
+        node_ref(&self.handle_node(handle))
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::List;
+    use std::{
+        cell::Cell,
+        panic::{self, AssertUnwindSafe},
+        rc::{Rc, Weak},
+    };
 
     #[test]
     fn basics() {
@@ -253,4 +924,525 @@ mod test {
         assert_eq!(iter.next_back(), None);
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn for_loops() {
+        let mut list = List::new();
+
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+
+        let mut seen = Vec::new();
+        for elem in list {
+            seen.push(elem);
+        }
+        assert_eq!(seen, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn from_iter_and_extend() {
+        let mut list: List<_> = vec![1, 2, 3].into_iter().collect();
+        list.extend(vec![4, 5]);
+
+        assert_eq!(&*list.peek_front().unwrap(), &1);
+        assert_eq!(&*list.peek_back().unwrap(), &5);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn std_traits() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        let mut copy = list.clone();
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert_eq!(list, copy);
+
+        // The clone is deep, changing it leaves the original alone
+        *copy.peek_front_mut().unwrap() = 10;
+        assert_eq!(copy.pop_back(), Some(3));
+        assert_eq!(format!("{:?}", copy), "[10, 2]");
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+
+        // Comparing goes through `Ref`s, a list can be compared with itself
+        assert_eq!(list, list);
+        assert!(list < copy);
+        assert_eq!(list.cmp(&list), std::cmp::Ordering::Equal);
+    }
+
+    #[test]
+    fn iter() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+
+        let mut iter = list.iter();
+        assert_eq!(*iter.next().unwrap(), 1);
+        assert_eq!(*iter.next().unwrap(), 2);
+        assert_eq!(*iter.next().unwrap(), 3);
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+
+        // Guards from both ends can be held at the same time
+        let mut iter = list.iter();
+        let front = iter.next().unwrap();
+        let back = iter.next_back().unwrap();
+        assert_eq!((*front, *back), (1, 3));
+        assert_eq!(*iter.next_back().unwrap(), 2);
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+
+        assert_eq!(&*list.peek_front().unwrap(), &1);
+        assert!(List::<i32>::new().iter().next().is_none());
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut list: List<_> = vec![1, 2, 3, 4].into_iter().collect();
+
+        let mut iter = list.iter_mut();
+        let mut first = iter.next().unwrap();
+        let mut last = iter.next_back().unwrap();
+        *first *= 10;
+        *last *= 10;
+        for mut elem in iter {
+            *elem += 1;
+        }
+        drop((first, last));
+
+        assert_eq!(format!("{:?}", list), "[10, 3, 4, 40]");
+
+        for mut elem in list.iter_mut().rev() {
+            *elem = -*elem;
+        }
+        assert_eq!(format!("{:?}", list), "[-10, -3, -4, -40]");
+    }
+
+    #[test]
+    fn borrowed_for_loops() {
+        let mut list: List<_> = vec![1, 2, 3].into_iter().collect();
+
+        for mut elem in &mut list {
+            *elem *= 2;
+        }
+
+        let mut seen = Vec::new();
+        for elem in &list {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![2, 4, 6]);
+    }
+
+    // Collects the list both ways round, so broken `prev` links show up
+    fn contents(list: &List<i32>) -> Vec<i32> {
+        let forward: Vec<_> = list.iter().map(|elem| *elem).collect();
+        let mut backward: Vec<_> = list.iter().rev().map(|elem| *elem).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        forward
+    }
+
+    #[test]
+    fn cursor() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+
+        let mut cursor = list.cursor_front();
+        assert_eq!(*cursor.current().unwrap(), 1);
+        assert!(cursor.peek_prev().is_none());
+        assert_eq!(*cursor.peek_next().unwrap(), 2);
+
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(*cursor.current().unwrap(), 3);
+
+        // Off the end onto the ghost, then round to the front again
+        cursor.move_next();
+        assert!(cursor.current().is_none());
+        assert_eq!(*cursor.peek_next().unwrap(), 1);
+        assert_eq!(*cursor.peek_prev().unwrap(), 3);
+        cursor.move_next();
+        assert_eq!(*cursor.current().unwrap(), 1);
+
+        let mut cursor = list.cursor_back();
+        assert_eq!(*cursor.current().unwrap(), 3);
+        cursor.move_prev();
+        assert_eq!(*cursor.current().unwrap(), 2);
+
+        let empty = List::<i32>::new();
+        let mut cursor = empty.cursor_front();
+        assert!(cursor.current().is_none());
+        cursor.move_prev();
+        assert!(cursor.current().is_none());
+        assert!(cursor.peek_next().is_none());
+    }
+
+    #[test]
+    fn cursor_mut_insert_and_remove() {
+        let mut list: List<_> = vec![2, 4].into_iter().collect();
+
+        let mut cursor = list.cursor_front_mut();
+        cursor.insert_before(1);
+        cursor.insert_after(3);
+        *cursor.current().unwrap() *= 10;
+        cursor.move_next();
+        cursor.move_next();
+        cursor.insert_after(5);
+        assert_eq!(contents(&list), vec![1, 20, 3, 4, 5]);
+
+        // At the ghost inserting wraps round to the ends
+        let mut cursor = list.cursor_back_mut();
+        cursor.move_next();
+        assert!(cursor.current().is_none());
+        cursor.insert_after(0);
+        cursor.insert_before(6);
+        assert_eq!(*cursor.peek_next().unwrap(), 0);
+        assert_eq!(*cursor.peek_prev().unwrap(), 6);
+        assert_eq!(contents(&list), vec![0, 1, 20, 3, 4, 5, 6]);
+
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(cursor.remove_current(), Some(0));
+        assert_eq!(*cursor.current().unwrap(), 1);
+        cursor.move_next();
+        assert_eq!(cursor.remove_current(), Some(20));
+        assert_eq!(*cursor.current().unwrap(), 3);
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.remove_current(), None);
+
+        let mut cursor = list.cursor_back_mut();
+        assert_eq!(cursor.remove_current(), Some(6));
+        assert!(cursor.current().is_none());
+        assert_eq!(contents(&list), vec![1, 3, 4, 5]);
+
+        let mut cursor = list.cursor_front_mut();
+        while cursor.remove_current().is_some() {}
+        assert_eq!(contents(&list), vec![]);
+        assert!(list.peek_front().is_none());
+        assert!(list.peek_back().is_none());
+    }
+
+    #[test]
+    fn cursor_mut_split() {
+        let mut list: List<_> = (1..=6).collect();
+
+        let mut cursor = list.cursor_front_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let back = cursor.split_after();
+        let front = cursor.split_before();
+        assert_eq!(*cursor.current().unwrap(), 3);
+        assert_eq!(contents(&front), vec![1, 2]);
+        assert_eq!(contents(&back), vec![4, 5, 6]);
+        assert_eq!(contents(&list), vec![3]);
+
+        // Nothing left on either side
+        let mut cursor = list.cursor_front_mut();
+        assert_eq!(contents(&cursor.split_after()), vec![]);
+        assert_eq!(contents(&cursor.split_before()), vec![]);
+
+        // The ghost splits off the whole list
+        cursor.move_next();
+        assert_eq!(contents(&cursor.split_after()), vec![3]);
+        assert_eq!(contents(&list), vec![]);
+    }
+
+    #[test]
+    fn cursor_mut_splice() {
+        let mut list: List<_> = vec![1, 5].into_iter().collect();
+
+        let mut cursor = list.cursor_front_mut();
+        cursor.splice_after(vec![2, 3].into_iter().collect());
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.splice_before(vec![4].into_iter().collect());
+        cursor.splice_before(List::new());
+        cursor.splice_after(List::new());
+        assert_eq!(*cursor.current().unwrap(), 5);
+        assert_eq!(contents(&list), vec![1, 2, 3, 4, 5]);
+
+        // At the ghost splicing goes on the ends
+        let mut cursor = list.cursor_back_mut();
+        cursor.move_next();
+        cursor.splice_after(vec![-1, 0].into_iter().collect());
+        cursor.splice_before(vec![6, 7].into_iter().collect());
+        assert_eq!(contents(&list), vec![-1, 0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // Into an empty list
+        let mut empty = List::new();
+        empty.cursor_front_mut().splice_after(list);
+        assert_eq!(contents(&empty).len(), 9);
+        assert_eq!(empty.pop_back(), Some(7));
+        assert_eq!(empty.pop_front(), Some(-1));
+    }
+
+    #[test]
+    fn handles() {
+        let mut list = List::new();
+        let two = list.push_front_handle(2);
+        let one = list.push_front_handle(1);
+        let three = list.push_back_handle(3);
+        assert_eq!(contents(&list), vec![1, 2, 3]);
+
+        assert!(list.move_to_front(&three));
+        assert!(list.move_to_front(&three));
+        assert_eq!(contents(&list), vec![3, 1, 2]);
+        assert!(list.move_to_front(&two));
+        assert_eq!(contents(&list), vec![2, 3, 1]);
+
+        let zero = list.insert_before(&two, 0).unwrap();
+        let four = list.insert_before(&one, 4).unwrap();
+        assert_eq!(contents(&list), vec![0, 2, 3, 4, 1]);
+
+        assert_eq!(*list.get(&four).unwrap(), 4);
+        *list.get_mut(&four).unwrap() = 40;
+
+        assert_eq!(list.remove(&three), Some(3));
+        assert_eq!(list.remove(&zero), Some(0));
+        assert_eq!(list.remove(&one), Some(1));
+        assert_eq!(contents(&list), vec![2, 40]);
+    }
+
+    #[test]
+    fn stale_handles() {
+        let mut list = List::new();
+        let one = list.push_back_handle(1);
+        let two = list.push_back_handle(2);
+        assert!(!one.is_stale());
+
+        assert_eq!(list.pop_front(), Some(1));
+        assert!(one.is_stale());
+        assert_eq!(list.remove(&one), None);
+        assert!(!list.move_to_front(&one));
+        assert_eq!(list.insert_before(&one, 5).unwrap_err(), 5);
+        assert!(list.get(&one).is_none());
+
+        let copy = two.clone();
+        assert_eq!(list.remove(&two), Some(2));
+        assert!(copy.is_stale());
+        assert_eq!(list.remove(&copy), None);
+        assert_eq!(contents(&list), vec![]);
+
+        let three = list.push_back_handle(3);
+        drop(list);
+        assert!(three.is_stale());
+    }
+
+    #[test]
+    fn handles_from_another_list() {
+        let mut list = List::new();
+        list.push_back(0);
+        let middle = list.push_back_handle(1);
+        list.push_back(2);
+
+        // Removing through another list would pull the node out from under
+        // `list`'s iterator
+        let mut other = List::new();
+        other.push_back(20);
+        let mut iter = list.iter();
+        iter.next();
+        assert_eq!(other.remove(&middle), None);
+        assert_eq!(iter.map(|elem| *elem).collect::<Vec<_>>(), vec![1, 2]);
+
+        assert!(!other.move_to_front(&middle));
+        assert_eq!(other.insert_before(&middle, 0).unwrap_err(), 0);
+        assert!(other.get(&middle).is_none());
+        assert!(other.get_mut(&middle).is_none());
+
+        // Also when the other list has handles of its own
+        let first = other.push_front_handle(10);
+        assert_eq!(other.remove(&middle), None);
+        assert!(!middle.is_stale());
+        assert_eq!(*other.get(&first).unwrap(), 10);
+        check_links(&list);
+        check_links(&other);
+        assert_eq!(contents(&list), vec![0, 1, 2]);
+        assert_eq!(contents(&other), vec![10, 20]);
+        assert_eq!(*list.get(&middle).unwrap(), 1);
+    }
+
+    #[test]
+    fn splitting_retires_handles() {
+        let mut list = List::new();
+        let one = list.push_back_handle(1);
+        let two = list.push_back_handle(2);
+
+        let mut back = list.cursor_front_mut().split_after();
+        assert_eq!(contents(&back), vec![2]);
+
+        // `two` moved to `back` and `one` stayed, but both handles are
+        // stale now and neither list takes them
+        assert!(one.is_stale());
+        assert!(two.is_stale());
+        assert_eq!(list.remove(&one), None);
+        assert_eq!(list.remove(&two), None);
+        assert_eq!(back.remove(&two), None);
+        assert!(!list.move_to_front(&one));
+        assert!(list.get(&one).is_none());
+        assert_eq!(contents(&list), vec![1]);
+
+        // Splitting the whole list off moves the handles along with it
+        let four = list.push_back_handle(4);
+        let mut cursor = list.cursor_front_mut();
+        cursor.move_prev();
+        let mut all = cursor.split_after();
+        assert!(!four.is_stale());
+        assert_eq!(list.remove(&four), None);
+        assert_eq!(all.remove(&four), Some(4));
+
+        // Handles made after the split work as usual
+        let three = back.push_back_handle(3);
+        assert_eq!(back.remove(&three), Some(3));
+
+        // Spliced in nodes' handles go stale with the list they came from
+        let five = back.push_back_handle(5);
+        list.cursor_back_mut().splice_after(back);
+        assert_eq!(contents(&list), vec![2, 5]);
+        assert!(five.is_stale());
+        assert_eq!(list.remove(&five), None);
+    }
+
+    // Counts how many elements are alive, so tests can tell whether
+    // dropping a list actually freed every node
+    struct Tracked {
+        live: Rc<Cell<usize>>,
+        value: i32,
+    }
+
+    impl Tracked {
+        fn new(live: &Rc<Cell<usize>>, value: i32) -> Self {
+            live.set(live.get() + 1);
+            Tracked {
+                live: live.clone(),
+                value,
+            }
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.live.set(self.live.get() - 1);
+        }
+    }
+
+    // Checks the ownership invariants: `prev` mirrors `next`, and every node
+    // has exactly one strong owner in front of it, plus `tail` for the last.
+    fn check_links<T>(list: &List<T>) {
+        let mut prev: Option<Rc<_>> = None;
+        let mut node = list.head.clone();
+        while let Some(curr) = node {
+            let back = curr.borrow().prev.as_ref().and_then(Weak::upgrade);
+            assert_eq!(back.is_some(), prev.is_some());
+            if let (Some(back), Some(prev)) = (back, &prev) {
+                assert!(Rc::ptr_eq(&back, prev));
+            }
+
+            let next = curr.borrow().next.clone();
+            // +1 for `curr` itself
+            let expected = if next.is_none() { 3 } else { 2 };
+            assert_eq!(Rc::strong_count(&curr), expected);
+            if next.is_none() {
+                assert!(Rc::ptr_eq(list.tail.as_ref().unwrap(), &curr));
+            }
+
+            prev = Some(curr);
+            node = next;
+        }
+        assert_eq!(prev.is_none(), list.tail.is_none());
+    }
+
+    #[test]
+    fn no_leaks() {
+        let live = Rc::new(Cell::new(0));
+        let mut list = List::new();
+
+        for i in 0..10 {
+            list.push_back(Tracked::new(&live, i));
+            list.push_front(Tracked::new(&live, -i));
+        }
+        check_links(&list);
+        assert_eq!(live.get(), 20);
+
+        list.pop_front();
+        list.pop_back();
+        check_links(&list);
+        assert_eq!(live.get(), 18);
+
+        drop(list);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn no_leaks_through_cursors_and_handles() {
+        let live = Rc::new(Cell::new(0));
+        let mut list: List<_> = (0..10).map(|i| Tracked::new(&live, i)).collect();
+        let handle = list.push_back_handle(Tracked::new(&live, 10));
+
+        let mut cursor = list.cursor_front_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let back = cursor.split_after();
+        cursor.remove_current();
+        cursor.insert_before(Tracked::new(&live, 11));
+        cursor.splice_after((20..25).map(|i| Tracked::new(&live, i)).collect());
+        check_links(&list);
+        check_links(&back);
+
+        // The handle doesn't keep its node alive
+        drop(back);
+        assert!(handle.is_stale());
+        assert_eq!(live.get(), 8);
+
+        let handle = list.push_front_handle(Tracked::new(&live, 30));
+        assert!(list.move_to_front(&handle));
+        list.insert_before(&handle, Tracked::new(&live, 31)).ok();
+        check_links(&list);
+        drop(list);
+        assert_eq!(live.get(), 0);
+        assert!(handle.is_stale());
+    }
+
+    #[test]
+    fn no_leaks_after_panic() {
+        let live = Rc::new(Cell::new(0));
+        let mut list: List<_> = (0..5).map(|i| Tracked::new(&live, i)).collect();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            for mut elem in list.iter_mut() {
+                elem.value *= 10;
+                if elem.value == 20 {
+                    panic!("bail out mid-iteration");
+                }
+            }
+        }));
+        assert!(result.is_err());
+
+        let values: Vec<_> = list.iter().map(|elem| elem.value).collect();
+        assert_eq!(values, vec![0, 10, 20, 3, 4]);
+        check_links(&list);
+
+        drop(list);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn no_cycles_without_unlinking() {
+        // Letting go of the head and tail without breaking any links
+        // still frees the whole chain, since the `prev` links are weak
+        let live = Rc::new(Cell::new(0));
+        let mut list: List<_> = (0..100).map(|i| Tracked::new(&live, i)).collect();
+
+        let head = list.head.take();
+        let tail = list.tail.take();
+        drop(list);
+        assert_eq!(live.get(), 100);
+
+        drop(tail);
+        drop(head);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn long_list() {
+        // Dropping must not recurse once per node
+        let list: List<_> = (0..100_000).collect();
+        drop(list);
+    }
 }
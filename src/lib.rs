@@ -3,3 +3,12 @@ pub mod second;
 pub mod third;
 pub mod fourth;
 pub mod fifth;
+pub mod lru;
+pub mod concurrent;
+pub mod blocking;
+pub mod channel;
+pub mod persistent_queue;
+pub mod arc_list;
+pub mod random_access;
+pub mod zipper;
+pub mod stream;
@@ -0,0 +1,293 @@
+/*
+An LRU cache
+------------
+
+Entries live in a `fourth::List` deque ordered by recency, most recently used
+at the front. A `HashMap` from each key to the `NodeHandle` of its entry lets
+us find an entry and move it to the front in O(1):
+
+    map:   a -> handle ---------+
+           b -> handle ---+     |
+                          v     v
+    list:  [front]  (b, 2) <-> (a, 1)  [back]
+
+Once the cache holds more than `capacity` entries the back of the deque, the
+least recently used entry, is evicted.
+*/
+
+use std::{
+    cell::{Ref, RefMut},
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    mem,
+};
+
+use crate::fourth::{self, NodeHandle};
+
+pub struct LruCache<K, V> {
+    map: HashMap<K, NodeHandle<(K, V)>>,
+    list: fourth::List<(K, V)>,
+    capacity: usize,
+    on_evict: Option<Box<dyn FnMut(K, V)>>,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        LruCache {
+            map: HashMap::with_capacity(capacity),
+            list: fourth::List::new(),
+            capacity,
+            on_evict: None,
+        }
+    }
+
+    // Called with every entry pushed out because the cache was full. Entries
+    // taken out through `pop_lru`, `remove` or by replacing their value are
+    // handed back to the caller instead.
+    pub fn set_on_evict(&mut self, on_evict: impl FnMut(K, V) + 'static) {
+        self.on_evict = Some(Box::new(on_evict));
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    // Inserts or updates `key` and marks it as most recently used. Returns
+    // the old value when the key was already cached.
+    pub fn put(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(handle) = self.map.get(&key) {
+            self.list.move_to_front(handle);
+            let mut entry = self.list.peek_front_mut().unwrap();
+            return Some(mem::replace(&mut entry.1, value));
+        }
+
+        let handle = self.list.push_front_handle((key.clone(), value));
+        self.map.insert(key, handle);
+
+        if self.map.len() > self.capacity {
+            let (key, value) = self.pop_lru().unwrap();
+            if let Some(on_evict) = &mut self.on_evict {
+                on_evict(key, value);
+            }
+        }
+        None
+    }
+
+    // Looks up `key` and marks it as most recently used.
+    pub fn get(&mut self, key: &K) -> Option<Ref<'_, V>> {
+        let handle = self.map.get(key)?;
+        self.list.move_to_front(handle);
+        self.list
+            .peek_front()
+            .map(|entry| Ref::map(entry, |(_, value)| value))
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<RefMut<'_, V>> {
+        let handle = self.map.get(key)?;
+        self.list.move_to_front(handle);
+        self.list
+            .peek_front_mut()
+            .map(|entry| RefMut::map(entry, |(_, value)| value))
+    }
+
+    // Looks up `key` without changing its recency.
+    pub fn peek(&self, key: &K) -> Option<Ref<'_, V>> {
+        let handle = self.map.get(key)?;
+        self.list
+            .get(handle)
+            .map(|entry| Ref::map(entry, |(_, value)| value))
+    }
+
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        let (key, value) = self.list.pop_back()?;
+        self.map.remove(&key);
+        Some((key, value))
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let handle = self.map.remove(key)?;
+        self.list.remove(&handle).map(|(_, value)| value)
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        while self.list.pop_front().is_some() {}
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+// Walks the entries from most to least recently used, without changing
+// their recency.
+pub struct Iter<'a, K, V>(fourth::Iter<'a, (K, V)>);
+
+impl<K, V> LruCache<K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter(self.list.iter())
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a LruCache<K, V> {
+    type Item = (Ref<'a, K>, Ref<'a, V>);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (Ref<'a, K>, Ref<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0
+            .next()
+            .map(|entry| Ref::map_split(entry, |(key, value)| (key, value)))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0
+            .next_back()
+            .map(|entry| Ref::map_split(entry, |(key, value)| (key, value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LruCache;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn keys(cache: &LruCache<&'static str, i32>) -> Vec<&'static str> {
+        cache.iter().map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn basics() {
+        let mut cache = LruCache::new(2);
+        assert!(cache.is_empty());
+        assert!(cache.get(&"a").is_none());
+
+        assert_eq!(cache.put("a", 1), None);
+        assert_eq!(cache.put("b", 2), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(keys(&cache), vec!["b", "a"]);
+
+        // Touching `a` makes `b` the one to go
+        assert_eq!(*cache.get(&"a").unwrap(), 1);
+        assert_eq!(keys(&cache), vec!["a", "b"]);
+        assert_eq!(cache.put("c", 3), None);
+        assert_eq!(keys(&cache), vec!["c", "a"]);
+        assert!(!cache.contains(&"b"));
+
+        // Updating a value refreshes it too
+        assert_eq!(cache.put("a", 10), Some(1));
+        assert_eq!(keys(&cache), vec!["a", "c"]);
+        *cache.get_mut(&"c").unwrap() += 1;
+        assert_eq!(format!("{:?}", cache), r#"{"c": 4, "a": 10}"#);
+    }
+
+    #[test]
+    fn peek_keeps_order() {
+        let mut cache = LruCache::new(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        assert_eq!(*cache.peek(&"a").unwrap(), 1);
+        assert!(cache.peek(&"d").is_none());
+        assert_eq!(keys(&cache), vec!["c", "b", "a"]);
+
+        let oldest: Vec<_> = cache.iter().rev().map(|(key, _)| *key).collect();
+        assert_eq!(oldest, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn pop_and_remove() {
+        let mut cache = LruCache::new(3);
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.put("c", 3);
+
+        assert_eq!(cache.pop_lru(), Some(("a", 1)));
+        assert_eq!(cache.remove(&"c"), Some(3));
+        assert_eq!(cache.remove(&"c"), None);
+        assert_eq!(keys(&cache), vec!["b"]);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.pop_lru(), None);
+        assert_eq!(cache.capacity(), 3);
+    }
+
+    #[test]
+    fn eviction_callback() {
+        let evicted = Rc::new(RefCell::new(Vec::new()));
+
+        let mut cache = LruCache::new(2);
+        let log = evicted.clone();
+        cache.set_on_evict(move |key, value| log.borrow_mut().push((key, value)));
+
+        cache.put("a", 1);
+        cache.put("b", 2);
+        cache.get(&"a");
+        cache.put("c", 3);
+        cache.put("d", 4);
+
+        // Explicit removals don't count as evictions
+        cache.pop_lru();
+        cache.remove(&"d");
+
+        assert_eq!(*evicted.borrow(), vec![("b", 2), ("a", 1)]);
+    }
+
+    #[test]
+    fn evicted_entries_are_freed() {
+        // Every cached value is a clone of `live`, so its strong count
+        // tracks how many values haven't been dropped yet
+        let live = Rc::new(());
+        let mut cache = LruCache::new(10);
+        for i in 0..100 {
+            cache.put(i % 25, live.clone());
+            if i % 3 == 0 {
+                cache.get(&(i % 7));
+            }
+        }
+        // Anything the cache let go of is gone, the rest are still cached
+        assert_eq!(cache.len(), 10);
+        assert_eq!(Rc::strong_count(&live), 11);
+
+        // The last key put in is certainly still there
+        let removed = cache.remove(&24).unwrap();
+        assert!(Rc::ptr_eq(&removed, &live));
+        assert!(!cache.contains(&24));
+        drop(removed);
+        assert_eq!(Rc::strong_count(&live), 10);
+
+        assert!(cache.pop_lru().is_some());
+        assert_eq!(cache.len(), 8);
+        assert_eq!(Rc::strong_count(&live), 9);
+
+        drop(cache);
+        assert_eq!(Rc::strong_count(&live), 1);
+    }
+}
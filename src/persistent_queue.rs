@@ -0,0 +1,481 @@
+/*
+A persistent queue
+------------------
+
+Okasaki's physicist's queue, built from `third::List`s. Elements are taken
+off a front list and added to a back list, which is kept in reverse:
+
+    queue:  1 2 3 4 5 6
+    front:  1 -> 2 -> 3 -> 4
+    back:   6 -> 5
+
+Once the back grows longer than the front, the two are rotated into a new
+front, `front ++ reverse(back)`, and the back starts over empty. The
+rotation is O(n), but it only happens after n cheap operations that pay for
+it, which makes every operation O(1) amortized.
+
+That argument breaks down for a persistent queue as-is: every version is
+still valid, so we could keep going back to the version just before a
+rotation and make it pay for the same rotation over and over. Okasaki's fix
+is laziness. A rotation doesn't build the new front, it builds a suspension
+that builds the new front when first forced, and remembers the result. All
+the versions that share the suspension share the work too:
+
+    v1 = ...snoc(x)           front: <suspended: f ++ reverse(b)>
+    v2 = v1.snoc(y)            \
+    v3 = v1.tail()              > all force the same suspension at most once
+    v4 = v1.snoc(z).tail()     /
+
+Taking the tail of a suspended front has to be suspended too. So that
+`head` doesn't have to force anything, we also keep a prefix of the front
+that has already been evaluated, and only force the front once the prefix
+runs out.
+*/
+
+use std::{
+    cell::{OnceCell, RefCell},
+    fmt,
+    rc::Rc,
+};
+
+use crate::third;
+
+pub struct Queue<T> {
+    // An evaluated prefix of the front, where heads come from
+    prefix: third::List<T>,
+    front: Rc<Susp<T>>,
+    front_len: usize,
+    back: third::List<T>,
+    back_len: usize,
+}
+
+// A suspended list, evaluated at most once.
+struct Susp<T> {
+    value: OnceCell<third::List<T>>,
+    // Taken once `value` is set
+    thunk: RefCell<Option<Thunk<T>>>,
+}
+
+enum Thunk<T> {
+    // `front ++ reverse(back)`
+    Rotate(third::List<T>, third::List<T>),
+    // The tail of another suspended list
+    Tail(Rc<Susp<T>>),
+}
+
+impl<T> Susp<T> {
+    fn ready(value: third::List<T>) -> Rc<Self> {
+        Rc::new(Susp {
+            value: OnceCell::from(value),
+            thunk: RefCell::new(None),
+        })
+    }
+
+    fn suspend(thunk: Thunk<T>) -> Rc<Self> {
+        Rc::new(Susp {
+            value: OnceCell::new(),
+            thunk: RefCell::new(Some(thunk)),
+        })
+    }
+}
+
+impl<T: Clone> Susp<T> {
+    fn force(self: &Rc<Self>) -> &third::List<T> {
+        if let Some(value) = self.value.get() {
+            return value;
+        }
+
+        // A front that was tailed many times is a long chain of `Tail`s.
+        // Walk down to the first one we can evaluate, then fill the values
+        // in on the way back up, rather than recursing down the chain.
+        let mut chain = vec![self.clone()];
+        loop {
+            let inner = match &*chain.last().unwrap().thunk.borrow() {
+                Some(Thunk::Tail(inner)) if inner.value.get().is_none() => inner.clone(),
+                _ => break,
+            };
+            chain.push(inner);
+        }
+
+        for susp in chain.iter().rev() {
+            let value = match susp.thunk.take().unwrap() {
+                Thunk::Rotate(front, back) => {
+                    let back: Vec<_> = back.iter().collect();
+                    front
+                        .iter()
+                        .chain(back.into_iter().rev())
+                        .cloned()
+                        .collect()
+                }
+                Thunk::Tail(inner) => inner.value.get().unwrap().tail(),
+            };
+            let _ = susp.value.set(value);
+        }
+
+        self.value.get().unwrap()
+    }
+}
+
+// Same problem as forcing: a long chain of `Tail`s would otherwise be
+// dropped recursively.
+impl<T> Drop for Susp<T> {
+    fn drop(&mut self) {
+        let mut thunk = self.thunk.get_mut().take();
+        while let Some(Thunk::Tail(inner)) = thunk {
+            match Rc::try_unwrap(inner) {
+                Ok(mut inner) => thunk = inner.thunk.get_mut().take(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Queue {
+            prefix: third::List::new(),
+            front: Susp::ready(third::List::new()),
+            front_len: 0,
+            back: third::List::new(),
+            back_len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front_len + self.back_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.front_len == 0
+    }
+
+    // The prefix is only ever empty when the whole queue is
+    pub fn head(&self) -> Option<&T> {
+        self.prefix.head()
+    }
+}
+
+impl<T: Clone> Queue<T> {
+    pub fn snoc(&self, elem: T) -> Queue<T> {
+        Queue {
+            prefix: self.prefix.clone(),
+            front: self.front.clone(),
+            front_len: self.front_len,
+            back: self.back.prepend(elem),
+            back_len: self.back_len + 1,
+        }
+        .check()
+    }
+
+    pub fn tail(&self) -> Queue<T> {
+        if self.is_empty() {
+            return Queue::new();
+        }
+
+        // No need to suspend anything if the front is already evaluated
+        let front = match self.front.value.get() {
+            Some(front) => Susp::ready(front.tail()),
+            None => Susp::suspend(Thunk::Tail(self.front.clone())),
+        };
+        Queue {
+            prefix: self.prefix.tail(),
+            front,
+            front_len: self.front_len - 1,
+            back: self.back.clone(),
+            back_len: self.back_len,
+        }
+        .check()
+    }
+
+    // Rotates once the back outgrows the front, and makes sure there's a
+    // prefix to take heads from.
+    fn check(mut self) -> Self {
+        if self.back_len > self.front_len {
+            let front = self.front.force().clone();
+            self.prefix = front.clone();
+            let back = std::mem::take(&mut self.back);
+            self.front = Susp::suspend(Thunk::Rotate(front, back));
+            self.front_len += self.back_len;
+            self.back_len = 0;
+        }
+        if self.prefix.head().is_none() {
+            self.prefix = self.front.force().clone();
+        }
+        self
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Like `third::List`, cloning is O(1) and the clone shares everything.
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            prefix: self.prefix.clone(),
+            front: self.front.clone(),
+            front_len: self.front_len,
+            back: self.back.clone(),
+            back_len: self.back_len,
+        }
+    }
+}
+
+impl<T: Clone> FromIterator<T> for Queue<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut queue = Queue::new();
+        queue.extend(iter);
+        queue
+    }
+}
+
+// Replaces this version with one that has every element added at the back,
+// other versions are unaffected.
+impl<T: Clone> Extend<T> for Queue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            *self = self.snoc(elem);
+        }
+    }
+}
+
+impl<T: Clone + fmt::Debug> fmt::Debug for Queue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone + PartialEq> PartialEq for Queue<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Clone + Eq> Eq for Queue<T> {}
+
+// Walks the front and then the back. The back is stored in reverse, so its
+// elements are gathered up front to be walked backwards.
+pub struct Iter<'a, T> {
+    front: third::Iter<'a, T>,
+    back: std::iter::Rev<std::vec::IntoIter<&'a T>>,
+}
+
+impl<T: Clone> Queue<T> {
+    // Forces the front, like taking the tail until the queue is empty would.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let back: Vec<_> = self.back.iter().collect();
+        Iter {
+            front: self.front.force().iter(),
+            back: back.into_iter().rev(),
+        }
+    }
+}
+
+impl<'a, T: Clone> IntoIterator for &'a Queue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.front.next().or_else(|| self.back.next())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Queue;
+    use std::{cell::Cell, collections::VecDeque, rc::Rc};
+
+    fn contents(queue: &Queue<i32>) -> Vec<i32> {
+        queue.iter().copied().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let queue = Queue::new();
+        assert_eq!(queue.head(), None);
+        assert!(queue.is_empty());
+
+        let queue = queue.snoc(1).snoc(2).snoc(3);
+        assert_eq!(queue.head(), Some(&1));
+        assert_eq!(queue.len(), 3);
+
+        let queue = queue.tail();
+        assert_eq!(queue.head(), Some(&2));
+
+        // Mix in some more to make sure nothing's corrupted
+        let queue = queue.snoc(4).snoc(5);
+        assert_eq!(contents(&queue), vec![2, 3, 4, 5]);
+
+        let queue = queue.tail().tail().tail();
+        assert_eq!(queue.head(), Some(&5));
+
+        let queue = queue.tail();
+        assert_eq!(queue.head(), None);
+        assert!(queue.is_empty());
+
+        // Make sure empty tail works
+        let queue = queue.tail();
+        assert_eq!(queue.head(), None);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn std_traits() {
+        let queue: Queue<_> = (1..=4).collect();
+        let mut longer = queue.clone();
+        longer.extend(vec![5, 6]);
+
+        assert_eq!(format!("{:?}", queue), "[1, 2, 3, 4]");
+        assert_eq!(format!("{:?}", longer.tail()), "[2, 3, 4, 5, 6]");
+        assert_eq!(queue, (1..=4).collect());
+        assert_ne!(queue, longer);
+        assert_eq!(Queue::<i32>::default(), Queue::new());
+
+        let mut seen = Vec::new();
+        for elem in &queue {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn old_versions_stay_valid() {
+        let mut versions = vec![Queue::new()];
+        for i in 0..50 {
+            let queue = versions.last().unwrap().snoc(i);
+            versions.push(queue);
+        }
+        for _ in 0..50 {
+            let queue = versions.last().unwrap().tail();
+            versions.push(queue);
+        }
+
+        // Every version still holds exactly what it did when it was made
+        for (i, queue) in versions.iter().enumerate() {
+            let expected: Vec<_> = if i <= 50 {
+                (0..i as i32).collect()
+            } else {
+                (i as i32 - 50..50).collect()
+            };
+            assert_eq!(contents(queue), expected);
+            assert_eq!(queue.len(), expected.len());
+            assert_eq!(queue.head(), expected.first());
+        }
+    }
+
+    #[test]
+    fn many_live_versions() {
+        // Grow a tree of versions, each made from a random older one, and
+        // check each against a `VecDeque` doing the same thing
+        let mut seed = 0x2545_f491_u32;
+        let mut rand = move |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as usize % bound
+        };
+
+        let mut versions = vec![(Queue::new(), VecDeque::new())];
+        let rounds = if cfg!(miri) { 200 } else { 5_000 };
+        for i in 0..rounds {
+            let (queue, model) = &versions[rand(versions.len())];
+            let (queue, mut model) = (queue.clone(), model.clone());
+
+            let queue = if rand(3) == 0 {
+                model.pop_front();
+                queue.tail()
+            } else {
+                model.push_back(i);
+                queue.snoc(i)
+            };
+
+            assert_eq!(queue.head(), model.front());
+            assert_eq!(queue.len(), model.len());
+            versions.push((queue, model));
+        }
+
+        for (queue, model) in &versions {
+            assert!(queue.iter().eq(model.iter()));
+        }
+    }
+
+    #[test]
+    fn rotations_are_shared() {
+        // Counts every clone, which is all the copying a rotation does
+        #[derive(Debug)]
+        struct Counted(Rc<Cell<usize>>);
+
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                self.0.set(self.0.get() + 1);
+                Counted(self.0.clone())
+            }
+        }
+
+        let clones = Rc::new(Cell::new(0));
+        let mut queue = Queue::new();
+        for _ in 0..300 {
+            queue = queue.snoc(Counted(clones.clone()));
+        }
+        // Find the version one `snoc` away from a rotation
+        while queue.back_len < queue.front_len {
+            queue = queue.snoc(Counted(clones.clone()));
+        }
+        let len = queue.len();
+        let before = clones.get();
+
+        // An eager queue would copy everything for every one of these, a
+        // lazy one only copies what it has to force
+        let rotated: Vec<_> = (0..10)
+            .map(|_| queue.snoc(Counted(clones.clone())))
+            .collect();
+        assert!(
+            clones.get() - before < len,
+            "{} clones",
+            clones.get() - before
+        );
+
+        // Versions sharing a rotation also share forcing it
+        let before = clones.get();
+        for _ in 0..10 {
+            let mut next = rotated[0].clone();
+            while !next.is_empty() {
+                next = next.tail();
+            }
+        }
+        assert!(
+            clones.get() - before <= len + 1,
+            "{} clones",
+            clones.get() - before
+        );
+        assert!(rotated.iter().all(|queue| queue.len() == len + 1));
+    }
+
+    #[test]
+    fn long_queue() {
+        // Dropping or forcing long chains of suspended tails mustn't
+        // recurse
+        let n = if cfg!(miri) { 1_000 } else { 200_000 };
+        let mut queue = Queue::new();
+        for i in 0..n {
+            queue = queue.snoc(i);
+        }
+        let tailed = (0..n / 4).fold(queue.clone(), |queue, _| queue.tail());
+        assert_eq!(tailed.head(), Some(&(n / 4)));
+        drop(tailed);
+
+        let tailed = (0..n / 4).fold(queue.clone(), |queue, _| queue.tail());
+        assert_eq!(tailed.iter().count(), n - n / 4);
+    }
+}
@@ -0,0 +1,395 @@
+/*
+A persistent random-access list
+-------------------------------
+
+Okasaki's skew-binary random-access list. Like `third::List` it's persistent
+and shares structure between versions, but instead of one node per element
+the elements are stored in complete binary trees, and the list is a
+`third::List` of those trees, smallest first:
+
+    list:    0 1 2 3 4 5 6 7
+    spine:   [1] -> [7]
+              |      |
+              0      1
+                   /   \
+                  2     5
+                 / \   / \
+                3   4 6   7
+
+Every tree holds 2^k - 1 elements, each tree is at least as big as the one
+before it, and only the first two can be the same size. The sizes read like
+the digits of a number in skew binary, where incrementing never carries more
+than one digit. That's what makes `prepend` O(1): when the first two trees
+are the same size, the new element becomes the root of a tree with them as
+its children, otherwise it goes on the front as a tree of its own.
+
+`tail` is the reverse, and O(1) too. `get` walks the O(log n) trees to find
+the right one, then goes down O(log n) levels inside it. `update` copies
+that same path and shares everything else with the old version.
+*/
+
+use std::{fmt, rc::Rc};
+
+use crate::third;
+
+pub struct List<T> {
+    // Trees along with their sizes, smallest first
+    spine: third::List<(usize, Rc<Tree<T>>)>,
+}
+
+enum Tree<T> {
+    Leaf(T),
+    // The element, then the left and right subtrees, in that order
+    Node(T, Rc<Tree<T>>, Rc<Tree<T>>),
+}
+
+impl<T> Tree<T> {
+    fn elem(&self) -> &T {
+        match self {
+            Tree::Leaf(elem) | Tree::Node(elem, _, _) => elem,
+        }
+    }
+
+    fn get(&self, mut size: usize, mut index: usize) -> &T {
+        let mut tree = self;
+        loop {
+            match tree {
+                Tree::Node(_, left, right) if index > 0 => {
+                    size /= 2;
+                    if index <= size {
+                        tree = left;
+                        index -= 1;
+                    } else {
+                        tree = right;
+                        index -= 1 + size;
+                    }
+                }
+                _ => return tree.elem(),
+            }
+        }
+    }
+}
+
+impl<T: Clone> Tree<T> {
+    // Copies the path down to `index`, the trees hanging off it are shared.
+    // Only O(log n) deep, so recursing is fine.
+    fn update(&self, size: usize, index: usize, elem: T) -> Tree<T> {
+        match self {
+            Tree::Leaf(_) => Tree::Leaf(elem),
+            Tree::Node(_, left, right) if index == 0 => {
+                Tree::Node(elem, left.clone(), right.clone())
+            }
+            Tree::Node(old, left, right) => {
+                let half = size / 2;
+                if index <= half {
+                    let left = left.update(half, index - 1, elem);
+                    Tree::Node(old.clone(), Rc::new(left), right.clone())
+                } else {
+                    let right = right.update(half, index - 1 - half, elem);
+                    Tree::Node(old.clone(), left.clone(), Rc::new(right))
+                }
+            }
+        }
+    }
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            spine: third::List::new(),
+        }
+    }
+
+    pub fn prepend(&self, elem: T) -> List<T> {
+        let mut digits = self.spine.iter();
+        if let (Some((size1, tree1)), Some((size2, tree2))) = (digits.next(), digits.next()) {
+            if size1 == size2 {
+                let tree = Tree::Node(elem, tree1.clone(), tree2.clone());
+                return List {
+                    spine: self
+                        .spine
+                        .tail()
+                        .tail()
+                        .prepend((1 + size1 + size2, Rc::new(tree))),
+                };
+            }
+        }
+        List {
+            spine: self.spine.prepend((1, Rc::new(Tree::Leaf(elem)))),
+        }
+    }
+
+    pub fn tail(&self) -> List<T> {
+        let spine = match self.spine.head() {
+            Some((size, tree)) => match &**tree {
+                Tree::Leaf(_) => self.spine.tail(),
+                // Dropping the root leaves its two subtrees behind
+                Tree::Node(_, left, right) => self
+                    .spine
+                    .tail()
+                    .prepend((size / 2, right.clone()))
+                    .prepend((size / 2, left.clone())),
+            },
+            None => third::List::new(),
+        };
+        List { spine }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.spine.head().map(|(_, tree)| tree.elem())
+    }
+
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        for (size, tree) in self.spine.iter() {
+            if index < *size {
+                return Some(tree.get(*size, index));
+            }
+            index -= size;
+        }
+        None
+    }
+
+    // O(log n), there's one size per tree to add up
+    pub fn len(&self) -> usize {
+        self.spine.iter().map(|(size, _)| size).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spine.head().is_none()
+    }
+}
+
+impl<T: Clone> List<T> {
+    // A new version with the element at `index` replaced. If `index` is out
+    // of bounds, the element is handed back.
+    pub fn update(&self, mut index: usize, elem: T) -> Result<List<T>, T> {
+        // The trees in front of the one we change have to be prepended again
+        let mut before = Vec::new();
+        let mut rest = self.spine.clone();
+        loop {
+            let Some((size, tree)) = rest.head() else {
+                return Err(elem);
+            };
+            if index < *size {
+                let tree = Rc::new(tree.update(*size, index, elem));
+                let mut spine = rest.tail().prepend((*size, tree));
+                for digit in before.into_iter().rev() {
+                    spine = spine.prepend(digit);
+                }
+                return Ok(List { spine });
+            }
+            index -= size;
+            before.push((*size, tree.clone()));
+            rest = rest.tail();
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cloning is O(1), the clone shares every tree.
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List {
+            spine: self.spine.clone(),
+        }
+    }
+}
+
+// Collecting builds the list in source order, the first element becomes the
+// head.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let elems: Vec<_> = iter.into_iter().collect();
+        elems
+            .into_iter()
+            .rev()
+            .fold(List::new(), |list, elem| list.prepend(elem))
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+// Walks the trees in order, each one root first, then its left subtree,
+// then its right.
+pub struct Iter<'a, T> {
+    spine: third::Iter<'a, (usize, Rc<Tree<T>>)>,
+    // Subtrees still to visit in the current tree, next one on top
+    stack: Vec<&'a Tree<T>>,
+}
+
+impl<T> List<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            spine: self.spine.iter(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = match self.stack.pop() {
+            Some(tree) => tree,
+            None => self.spine.next()?.1.as_ref(),
+        };
+        if let Tree::Node(_, left, right) = tree {
+            self.stack.push(right);
+            self.stack.push(left);
+        }
+        Some(tree.elem())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::List;
+
+    fn sizes<T>(list: &List<T>) -> Vec<usize> {
+        list.spine.iter().map(|(size, _)| *size).collect()
+    }
+
+    #[test]
+    fn basics() {
+        let list = List::new();
+        assert_eq!(list.head(), None);
+        assert!(list.is_empty());
+
+        let list = list.prepend(1).prepend(2).prepend(3);
+        assert_eq!(list.head(), Some(&3));
+        assert_eq!(list.len(), 3);
+
+        let list = list.tail();
+        assert_eq!(list.head(), Some(&2));
+
+        let list = list.tail();
+        assert_eq!(list.head(), Some(&1));
+
+        let list = list.tail();
+        assert_eq!(list.head(), None);
+
+        // Make sure empty tail works
+        let list = list.tail();
+        assert_eq!(list.head(), None);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn skew_binary_shape() {
+        // 0..8 is a tree of one, then a tree of seven
+        let list: List<_> = (0..8).collect();
+        assert_eq!(sizes(&list), vec![1, 7]);
+
+        // Prepending to two trees of the same size merges them
+        let list = list.tail().prepend(-1).prepend(-2);
+        assert_eq!(sizes(&list), vec![1, 1, 7]);
+        let list = list.prepend(-3);
+        assert_eq!(sizes(&list), vec![3, 7]);
+        let list = list.prepend(-4);
+        assert_eq!(sizes(&list), vec![1, 3, 7]);
+
+        // And taking the tail splits them up again
+        assert_eq!(sizes(&list.tail().tail()), vec![1, 1, 7]);
+    }
+
+    #[test]
+    fn get_and_update() {
+        for len in 0..40 {
+            let list: List<_> = (0..len).collect();
+            assert_eq!(list.len(), len);
+            assert_eq!(
+                list.iter().copied().collect::<Vec<_>>(),
+                (0..len).collect::<Vec<_>>()
+            );
+            assert_eq!(list.get(len), None);
+
+            for i in 0..len {
+                assert_eq!(list.get(i), Some(&i));
+
+                let updated = list.update(i, 100).unwrap();
+                let mut expected: Vec<_> = (0..len).collect();
+                expected[i] = 100;
+                assert_eq!(updated.iter().copied().collect::<Vec<_>>(), expected);
+                assert_eq!(updated.get(i), Some(&100));
+            }
+            assert_eq!(list.update(len, 100).err(), Some(100));
+
+            // The old version never changed
+            assert_eq!(
+                list.iter().copied().collect::<Vec<_>>(),
+                (0..len).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn update_shares_structure() {
+        let list: List<_> = (0..100).collect();
+        let updated = list.update(0, -1).unwrap();
+
+        // Only the first tree was touched, the rest of the spine is shared
+        assert!(updated.spine.tail().ptr_eq(&list.spine.tail()));
+
+        // Deeper in, only the trees in front get put back on the spine
+        let updated = list.update(99, -1).unwrap();
+        let digits = list.spine.iter().count();
+        assert_eq!(updated.spine.iter().count(), digits);
+        assert!(!updated.spine.shares_tail_with(&list.spine));
+        assert_eq!(list.get(99), Some(&99));
+        assert_eq!(updated.get(99), Some(&-1));
+    }
+
+    #[test]
+    fn std_traits() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::default()), "[]");
+        assert_eq!(list, copy);
+        assert_ne!(list, list.tail());
+
+        let mut seen = Vec::new();
+        for elem in &list {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn long_list() {
+        let n = if cfg!(miri) { 1_000 } else { 1_000_000 };
+        let list: List<_> = (0..n).collect();
+        assert_eq!(list.get(n - 1), Some(&(n - 1)));
+        assert_eq!(list.get(n / 3), Some(&(n / 3)));
+        let list = list.update(n / 2, 0).unwrap();
+        assert_eq!(list.get(n / 2), Some(&0));
+    }
+}
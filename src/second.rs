@@ -1,60 +1,182 @@
+/*
+The list caches a pointer to its last node, so `CursorMut::splice_after` can
+link another list in without walking it. As explained for `fifth::List`, a
+cached tail doesn't mix with `Box` links, so all the links are raw pointers
+and nodes only become a `Box` again when they're freed.
+*/
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::NonNull;
+
 pub struct List<T> {
     head: Link<T>,
+    tail: Link<T>,
+    // We own `T`s through the raw pointers, let the drop checker know
+    _boo: PhantomData<T>,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+type Link<T> = Option<NonNull<Node<T>>>;
 
 struct Node<T> {
     elem: T,
     next: Link<T>,
 }
 
+impl<T> Node<T> {
+    fn new(elem: T, next: Link<T>) -> NonNull<Self> {
+        unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(Node { elem, next }))) }
+    }
+}
+
 impl<T> List<T> {
     pub fn new() -> Self {
-        List { head: None }
+        List {
+            head: None,
+            tail: None,
+            _boo: PhantomData,
+        }
     }
 
     pub fn push(&mut self, elem: T) {
-        let new_node = Box::new(Node {
-            elem: elem,
-            next: self.head.take(),
-        });
+        let new_node = Node::new(elem, self.head);
+        if self.tail.is_none() {
+            self.tail = Some(new_node);
+        }
 
         self.head = Some(new_node);
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        self.head.take().map(|node| {
+        self.head.map(|node| unsafe {
+            let node = Box::from_raw(node.as_ptr());
             self.head = node.next;
+            if self.head.is_none() {
+                self.tail = None;
+            }
             node.elem
         })
     }
 
     pub fn peek(&self) -> Option<&T> {
-        self.head.as_ref().map(|node| &node.elem)
+        self.head.map(|node| unsafe { &(*node.as_ptr()).elem })
     }
 
     pub fn peek_mut(&mut self) -> Option<&mut T> {
-        self.head.as_mut().map(|node| &mut node.elem)
+        self.head.map(|node| unsafe { &mut (*node.as_ptr()).elem })
     }
 }
 
+// Owned like a `Box`, so as thread-safe as `T` is, see `fifth::List`.
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+unsafe impl<'a, T: Sync> Send for Cursor<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Cursor<'a, T> {}
+
+unsafe impl<'a, T: Send> Send for CursorMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for CursorMut<'a, T> {}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Collecting keeps the iterator's order, so `iter().collect()` round-trips
+// and the first element ends up on top of the stack.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        for elem in iter {
+            let new_tail = Node::new(elem, None);
+            match list.tail {
+                Some(old_tail) => unsafe { (*old_tail.as_ptr()).next = Some(new_tail) },
+                None => list.head = Some(new_tail),
+            }
+            list.tail = Some(new_tail);
+        }
+        list
+    }
+}
+
+// Extending behaves like a `push` loop, the last element ends up on top.
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+// Pops one node at a time, there's no recursive drop through the links to
+// blow the stack on long lists.
 impl<T> Drop for List<T> {
     fn drop(&mut self) {
-        let mut curr_link = self.head.take();
-        while let Some(mut boxed_node) = curr_link {
-            curr_link = boxed_node.next.take()
-            // boxed_node goes out of scope and gets dropped here;
-            // but it's Node's `next` field has been set to `None`
-            // so no unbounded recursion occurs.
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+// Hashed like `first::List`, the elements and then the length.
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for elem in self.iter() {
+            elem.hash(state);
+            len += 1;
         }
+        state.write_usize(len);
     }
 }
 
 pub struct IntoIter<T>(List<T>);
 
-impl<T> List<T> {
-    pub fn into_iter(self) -> IntoIter<T> {
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
         IntoIter(self)
     }
 }
@@ -77,20 +199,29 @@ impl<T> List<T> {
     // We declare a fresh lifetime here for the *exact* borrow that
     // creates the iter. Now &self needs to be valid as long as the
     // Iter is around
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            next: self.head.as_deref(),
+            next: self.head.map(|node| unsafe { &*node.as_ptr() }),
         }
     }
 }
 
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
 // We *do* have a lifetime here, because Iter has one that we need to define
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.next.map(|node| {
-            self.next = node.next.as_deref();
+            self.next = node.next.map(|node| unsafe { &*node.as_ptr() });
             &node.elem
         })
     }
@@ -101,24 +232,168 @@ pub struct IterMut<'a, T> {
 }
 
 impl<T> List<T> {
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
-            next: self.head.as_deref_mut(),
+            next: self.head.map(|node| unsafe { &mut *node.as_ptr() }),
         }
     }
 }
 
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
 impl<'a, T> Iterator for IterMut<'a, T> {
     type Item = &'a mut T;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.next.take().map(|node| {
-            self.next = node.next.as_deref_mut();
+            self.next = node.next.map(|node| unsafe { &mut *node.as_ptr() });
             &mut node.elem
         })
     }
 }
 
+/*
+Cursors start out on a "ghost" position in front of the head, and every
+operation acts on what comes *after* the cursor, since a singly-linked node
+can't reach the node before it:
+
+    cursor at ghost:  [head] -> A -> B -> C
+                      ^
+    move_next:        [head] -> A -> B -> C
+                                ^
+    insert_after(X):  [head] -> A -> X -> B -> C
+                                ^
+
+A cursor can't step back, and once it's on the last node `move_next` leaves
+it there.
+*/
+pub struct Cursor<'a, T> {
+    current: Option<&'a T>,
+    next: Option<&'a Node<T>>,
+}
+
+impl<T> List<T> {
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor {
+            current: None,
+            next: self.head.map(|node| unsafe { &*node.as_ptr() }),
+        }
+    }
+}
+
+impl<'a, T> Cursor<'a, T> {
+    pub fn current(&self) -> Option<&'a T> {
+        self.current
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.next.map(|node| &node.elem)
+    }
+
+    pub fn move_next(&mut self) {
+        if let Some(node) = self.next {
+            self.current = Some(&node.elem);
+            self.next = node.next.map(|node| unsafe { &*node.as_ptr() });
+        }
+    }
+}
+
+pub struct CursorMut<'a, T> {
+    list: &'a mut List<T>,
+    // `None` on the ghost
+    current: Link<T>,
+}
+
+impl<T> List<T> {
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            list: self,
+            current: None,
+        }
+    }
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    // The `next` link of the current node, or the list's `head` while we're
+    // on the ghost.
+    fn next_link(&mut self) -> &mut Link<T> {
+        match self.current {
+            Some(node) => unsafe { &mut (*node.as_ptr()).next },
+            None => &mut self.list.head,
+        }
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.current
+            .map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        self.next_link()
+            .map(|node| unsafe { &mut (*node.as_ptr()).elem })
+    }
+
+    pub fn move_next(&mut self) {
+        if let Some(next) = *self.next_link() {
+            self.current = Some(next);
+        }
+    }
+
+    pub fn insert_after(&mut self, elem: T) {
+        let link = self.next_link();
+        let new_node = Node::new(elem, *link);
+        if link.replace(new_node).is_none() {
+            self.list.tail = Some(new_node);
+        }
+    }
+
+    pub fn remove_next(&mut self) -> Option<T> {
+        let link = self.next_link();
+        let node = unsafe { Box::from_raw(link.take()?.as_ptr()) };
+        *link = node.next;
+        if node.next.is_none() {
+            self.list.tail = self.current;
+        }
+        Some(node.elem)
+    }
+
+    // Everything after the cursor is moved out into a new list.
+    pub fn split_after(&mut self) -> List<T> {
+        let head = self.next_link().take();
+        let tail = match head {
+            Some(_) => mem::replace(&mut self.list.tail, self.current),
+            None => None,
+        };
+        List {
+            head,
+            tail,
+            _boo: PhantomData,
+        }
+    }
+
+    // Links `list` in right after the cursor. Its cached tail is linked to
+    // whatever came after the cursor, so this is O(1) too.
+    pub fn splice_after(&mut self, mut list: List<T>) {
+        let (head, tail) = match (list.head.take(), list.tail.take()) {
+            (Some(head), Some(tail)) => (head, tail),
+            _ => return,
+        };
+
+        let rest = self.next_link().replace(head);
+        unsafe { (*tail.as_ptr()).next = rest };
+        if rest.is_none() {
+            self.list.tail = Some(tail);
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::List;
@@ -164,9 +439,9 @@ mod test {
         assert_eq!(list.peek(), Some(&3));
         assert_eq!(list.peek_mut(), Some(&mut 3));
 
-        list.peek_mut().map(|value| {
-            *value = 42;
-        });
+        if let Some(value) = list.peek_mut() {
+            *value = 42
+        }
         assert_eq!(list.peek(), Some(&42));
         assert_eq!(list.pop(), Some(42));
     }
@@ -215,4 +490,264 @@ mod test {
         assert_eq!(iter.next(), Some(&mut 1));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn for_loops() {
+        let mut list = List::new();
+
+        list.push(1);
+        list.push(2);
+        list.push(3);
+
+        for elem in &mut list {
+            *elem *= 10;
+        }
+
+        let mut seen = Vec::new();
+        for elem in &list {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![30, 20, 10]);
+
+        let mut seen = Vec::new();
+        for elem in list {
+            seen.push(elem);
+        }
+        assert_eq!(seen, vec![30, 20, 10]);
+    }
+
+    #[test]
+    fn from_iter() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(list.peek(), Some(&1));
+
+        let copy: List<_> = list.iter().copied().collect();
+        assert_eq!(copy.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn extend() {
+        let mut list: List<_> = vec![1, 2].into_iter().collect();
+        list.extend(vec![3, 4]);
+
+        assert_eq!(list.pop(), Some(4));
+        assert_eq!(list.pop(), Some(3));
+        assert_eq!(list.pop(), Some(1));
+        assert_eq!(list.pop(), Some(2));
+        assert_eq!(list.pop(), None);
+    }
+
+    #[test]
+    fn std_traits() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.clone(), list);
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert!(list < List::from_iter(vec![1, 3]));
+    }
+
+    #[test]
+    fn cursor() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+
+        let mut cursor = list.cursor();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&1));
+
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&1));
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&3));
+        assert_eq!(cursor.peek_next(), None);
+
+        // Stays put on the last node
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&3));
+
+        let empty = List::<i32>::new();
+        let mut cursor = empty.cursor();
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+    }
+
+    #[test]
+    fn cursor_mut_ordered_insert() {
+        fn insert_sorted(list: &mut List<i32>, elem: i32) {
+            let mut cursor = list.cursor_mut();
+            while cursor.peek_next().is_some_and(|next| *next < elem) {
+                cursor.move_next();
+            }
+            cursor.insert_after(elem);
+        }
+
+        let mut list = List::new();
+        for elem in [5, 1, 4, 2, 3, 0, 6] {
+            insert_sorted(&mut list, elem);
+        }
+        assert_eq!(list, (0..7).collect());
+    }
+
+    #[test]
+    fn cursor_mut_edit() {
+        let mut list: List<_> = (1..=5).collect();
+
+        let mut cursor = list.cursor_mut();
+        assert_eq!(cursor.current(), None);
+
+        // Removing from the ghost pops the head
+        assert_eq!(cursor.remove_next(), Some(1));
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&mut 2));
+        *cursor.current().unwrap() = 20;
+
+        assert_eq!(cursor.remove_next(), Some(3));
+        assert_eq!(cursor.peek_next(), Some(&mut 4));
+        cursor.insert_after(30);
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&mut 30));
+
+        assert_eq!(list, vec![20, 30, 4, 5].into_iter().collect());
+
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.remove_next(), None);
+    }
+
+    #[test]
+    fn cursor_mut_split() {
+        let mut list: List<_> = (1..=6).collect();
+
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let mut back = cursor.split_after();
+        assert_eq!(cursor.peek_next(), None);
+        assert_eq!(list, (1..=2).collect());
+        assert_eq!(back, (3..=6).collect());
+
+        // Splitting at the end leaves an empty list
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.split_after(), List::new());
+
+        // Splitting at the ghost takes everything
+        let all = back.cursor_mut().split_after();
+        assert_eq!(back.peek(), None);
+        assert_eq!(all.iter().count(), 4);
+    }
+
+    #[test]
+    fn cursor_mut_splice() {
+        let mut list: List<_> = (1..=3).collect();
+
+        // Splicing at the ghost prepends
+        let mut cursor = list.cursor_mut();
+        cursor.splice_after((7..=8).collect());
+        assert_eq!(cursor.peek_next(), Some(&mut 7));
+        assert_eq!(list, vec![7, 8, 1, 2, 3].into_iter().collect());
+
+        // In the middle, the rest of the list follows the spliced one
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.splice_after((4..=5).collect());
+        assert_eq!(cursor.peek_next(), Some(&mut 4));
+        assert_eq!(list, vec![7, 8, 4, 5, 1, 2, 3].into_iter().collect());
+
+        // At the end, it's appended
+        let mut cursor = list.cursor_mut();
+        for _ in 0..7 {
+            cursor.move_next();
+        }
+        cursor.splice_after((9..=9).collect());
+        assert_eq!(cursor.peek_next(), Some(&mut 9));
+        assert_eq!(list, vec![7, 8, 4, 5, 1, 2, 3, 9].into_iter().collect());
+
+        // An empty list changes nothing
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.splice_after(List::new());
+        assert_eq!(cursor.peek_next(), Some(&mut 8));
+        list.cursor_mut().splice_after(List::new());
+        assert_eq!(list, vec![7, 8, 4, 5, 1, 2, 3, 9].into_iter().collect());
+
+        // Splitting and splicing back in round-trips
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        let back = cursor.split_after();
+        cursor.splice_after(back);
+        assert_eq!(list, vec![7, 8, 4, 5, 1, 2, 3, 9].into_iter().collect());
+    }
+
+    #[test]
+    fn cursor_mut_keeps_tail() {
+        // Splices `list` in between `0` and `100`, which only comes out
+        // right if the cached tail really is its last node
+        fn spliced(list: List<i32>) -> Vec<i32> {
+            let mut outer: List<_> = vec![0, 100].into_iter().collect();
+            let mut cursor = outer.cursor_mut();
+            cursor.move_next();
+            cursor.splice_after(list);
+            outer.into_iter().collect()
+        }
+
+        let mut list = List::new();
+        list.push(1);
+        assert_eq!(spliced(list), vec![0, 1, 100]);
+
+        let mut list: List<_> = (1..=2).collect();
+        list.pop();
+        list.pop();
+        list.push(3);
+        assert_eq!(spliced(list), vec![0, 3, 100]);
+
+        // Inserting and removing at the end
+        let mut list: List<_> = (1..=2).collect();
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.insert_after(3);
+        assert_eq!(spliced(list), vec![0, 1, 2, 3, 100]);
+
+        let mut list: List<_> = (1..=3).collect();
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.remove_next(), Some(3));
+        assert_eq!(spliced(list), vec![0, 1, 2, 100]);
+
+        let mut list: List<_> = (1..=1).collect();
+        assert_eq!(list.cursor_mut().remove_next(), Some(1));
+        assert_eq!(spliced(list), vec![0, 100]);
+
+        // Both halves of a split
+        let mut list: List<_> = (1..=4).collect();
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        let back = cursor.split_after();
+        assert_eq!(spliced(back), vec![0, 2, 3, 4, 100]);
+        assert_eq!(spliced(list), vec![0, 1, 100]);
+
+        // Splicing at the end moves the tail to the spliced list's
+        let mut list: List<_> = (1..=2).collect();
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.splice_after((3..=4).collect());
+        assert_eq!(spliced(list), vec![0, 1, 2, 3, 4, 100]);
+    }
+
+    #[test]
+    fn send_sync() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<List<i32>>();
+        is_send_sync::<super::Iter<'_, i32>>();
+        is_send_sync::<super::IterMut<'_, i32>>();
+        is_send_sync::<super::CursorMut<'_, i32>>();
+    }
 }
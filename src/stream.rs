@@ -0,0 +1,542 @@
+/*
+A lazy persistent stream
+------------------------
+
+`third::List` with the tails left unevaluated until someone asks for them.
+Every cell of a stream is an `Rc`-shared suspension that computes its
+contents the first time it's forced and remembers them after that, so
+clones and tails share the work as well as the memory:
+
+    nats = 0 -> <unforced>
+
+    nats.tail().head()
+    nats = 0 -> 1 -> <unforced>
+
+    nats.tail().tail().head()
+    nats = 0 -> 1 -> 2 -> <unforced>
+
+That's what lets a stream be infinite, or hold elements that are expensive
+to compute, and only pay for what gets looked at.
+
+A suspension produces a whole `Stream`, which may itself be unforced. Rather
+than force that one recursively, forcing a cell loops, taking over the
+suspensions of freshly made streams as it goes, and leaves a pointer to
+streams that are shared. Combinators like `filter` loop too, so forcing is
+stack-safe however many elements get skipped.
+
+Dropping is iterative too. A forced chain of cells is followed in a loop,
+like for `third::List`, but an unforced cell holds the rest of the stream
+inside its closure, out of reach. Dropping the closure drops that stream,
+which may drop a closure holding another stream, and so on. So while one
+cell is being dropped, any other cell that dies on the same thread isn't
+dropped right away, it's put on a list for the first drop to get to after
+it's done with its own:
+
+    drop(a)                   a's closure holds b, b's holds c
+      drops a's closure       b dies, put on the list
+    drops b                   c dies, put on the list
+    drops c
+
+A cell put on the list can outlive whatever was being dropped when it died,
+for instance a local in some element's `Drop` that builds and drops a stream
+of its own. That's only fine if the cell doesn't borrow anything, so a stream
+only holds `'static` elements, the same as its closures.
+*/
+
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    rc::Rc,
+};
+
+use crate::third;
+
+/// Elements can't borrow anything, since dropping a cell may be put off:
+///
+/// ```compile_fail
+/// use too_many_lists::stream::Stream;
+///
+/// let local = 1;
+/// let stream = Stream::cons(&local, Stream::empty);
+/// ```
+pub struct Stream<T: 'static> {
+    cell: Rc<Lazy<T>>,
+}
+
+type Thunk<T> = Box<dyn FnOnce() -> Stream<T>>;
+
+struct Lazy<T: 'static> {
+    value: OnceCell<State<T>>,
+    // Taken when the cell is forced
+    thunk: Cell<Option<Thunk<T>>>,
+}
+
+enum State<T: 'static> {
+    Nil,
+    Cons(T, Stream<T>),
+    // Whatever another, shared, stream turns out to be
+    Same(Stream<T>),
+}
+
+impl<T: 'static> Stream<T> {
+    fn ready(state: State<T>) -> Self {
+        Stream {
+            cell: Rc::new(Lazy {
+                value: OnceCell::from(state),
+                thunk: Cell::new(None),
+            }),
+        }
+    }
+
+    pub fn empty() -> Self {
+        Stream::ready(State::Nil)
+    }
+
+    // A stream that starts with `elem`, followed by whatever `tail` returns
+    // once it's needed.
+    pub fn cons(elem: T, tail: impl FnOnce() -> Stream<T> + 'static) -> Self {
+        Stream::ready(State::Cons(elem, Stream::lazy(tail)))
+    }
+
+    // A stream that isn't built until it's needed.
+    pub fn lazy(stream: impl FnOnce() -> Stream<T> + 'static) -> Self {
+        Stream {
+            cell: Rc::new(Lazy {
+                value: OnceCell::new(),
+                thunk: Cell::new(Some(Box::new(stream))),
+            }),
+        }
+    }
+
+    // Forces this cell and returns the first element and the rest.
+    fn force(&self) -> Option<(&T, &Stream<T>)> {
+        let mut stream = self;
+        loop {
+            match stream.cell.evaluate() {
+                State::Nil => return None,
+                State::Cons(elem, rest) => return Some((elem, rest)),
+                State::Same(other) => stream = other,
+            }
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.force().map(|(elem, _)| elem)
+    }
+
+    // Forces the head, but not the tail itself.
+    pub fn tail(&self) -> Stream<T> {
+        match self.force() {
+            Some((_, rest)) => rest.clone(),
+            None => Stream::empty(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.force().is_none()
+    }
+
+    // Like `Iterator::unfold`: `f` turns a state into an element and the
+    // next state, until it returns `None`.
+    pub fn unfold<S: 'static>(state: S, f: impl Fn(S) -> Option<(T, S)> + 'static) -> Self {
+        fn go<T: 'static, S: 'static>(state: S, f: Rc<dyn Fn(S) -> Option<(T, S)>>) -> Stream<T> {
+            Stream::lazy(move || match f(state) {
+                Some((elem, state)) => Stream::cons(elem, move || go(state, f)),
+                None => Stream::empty(),
+            })
+        }
+        go(state, Rc::new(f))
+    }
+
+    pub fn map<U: 'static>(&self, f: impl Fn(&T) -> U + 'static) -> Stream<U> {
+        fn go<T: 'static, U: 'static>(stream: Stream<T>, f: Rc<dyn Fn(&T) -> U>) -> Stream<U> {
+            Stream::lazy(move || match stream.force() {
+                Some((elem, rest)) => {
+                    let rest = rest.clone();
+                    Stream::cons(f(elem), move || go(rest, f))
+                }
+                None => Stream::empty(),
+            })
+        }
+        go(self.clone(), Rc::new(f))
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: Some(self) }
+    }
+}
+
+impl<T: Clone + 'static> Stream<T> {
+    // `seed`, `f(seed)`, `f(f(seed))`, and so on. Each one is computed when
+    // it's first looked at.
+    pub fn iterate(seed: T, f: impl Fn(&T) -> T + 'static) -> Self {
+        Stream::cons(seed.clone(), move || {
+            Stream::unfold(seed, move |prev| {
+                let next = f(&prev);
+                Some((next.clone(), next))
+            })
+        })
+    }
+
+    pub fn take(&self, n: usize) -> Self {
+        if n == 0 {
+            return Stream::empty();
+        }
+        let stream = self.clone();
+        Stream::lazy(move || match stream.force() {
+            Some((elem, rest)) => {
+                let rest = rest.clone();
+                Stream::cons(elem.clone(), move || rest.take(n - 1))
+            }
+            None => Stream::empty(),
+        })
+    }
+
+    pub fn filter(&self, pred: impl Fn(&T) -> bool + 'static) -> Self {
+        fn go<T: Clone + 'static>(stream: Stream<T>, pred: Rc<dyn Fn(&T) -> bool>) -> Stream<T> {
+            Stream::lazy(move || {
+                // Skip ahead in a loop, not by recursing
+                let mut stream = stream;
+                loop {
+                    let next = match stream.force() {
+                        Some((elem, rest)) if pred(elem) => {
+                            let (elem, rest) = (elem.clone(), rest.clone());
+                            return Stream::cons(elem, move || go(rest, pred));
+                        }
+                        Some((_, rest)) => rest.clone(),
+                        None => return Stream::empty(),
+                    };
+                    stream = next;
+                }
+            })
+        }
+        go(self.clone(), Rc::new(pred))
+    }
+
+    // Forces the whole stream, so it had better be finite.
+    pub fn to_list(&self) -> third::List<T> {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: 'static> Lazy<T> {
+    fn evaluate(&self) -> &State<T> {
+        if let Some(state) = self.value.get() {
+            return state;
+        }
+
+        let mut thunk = self.thunk.take().expect("stream depends on its own value");
+        let state = loop {
+            let stream = thunk();
+            match Rc::try_unwrap(stream.cell) {
+                // Nobody else has seen the stream we got back, so we can take
+                // over whatever it holds, or its suspension
+                Ok(mut lazy) => match lazy.value.take() {
+                    Some(state) => break state,
+                    None => thunk = lazy.thunk.take().unwrap(),
+                },
+                Err(cell) => break State::Same(Stream { cell }),
+            }
+        };
+
+        // A thunk that forces its own cell panics above, so nothing else
+        // can have filled it in
+        let _ = self.value.set(state);
+        self.value.get().unwrap()
+    }
+}
+
+// Anything at all, so that cells of every `T` can wait on the same list.
+trait Pending {}
+
+impl<X> Pending for X {}
+
+thread_local! {
+    // `Some` while a cell is being dropped on this thread
+    static PENDING: RefCell<Option<Vec<Box<dyn Pending>>>> = const { RefCell::new(None) };
+}
+
+// Ends the outermost drop, even if dropping something panicked. Whatever is
+// still pending is dropped the usual way then.
+struct Draining;
+
+impl Drop for Draining {
+    fn drop(&mut self) {
+        let pending = PENDING.with(|pending| pending.borrow_mut().take());
+        drop(pending);
+    }
+}
+
+impl<T: 'static> Drop for Lazy<T> {
+    fn drop(&mut self) {
+        let (mut next, thunk) = (self.value.take(), self.thunk.take());
+        if next.is_none() && thunk.is_none() {
+            return;
+        }
+
+        // The thread is shutting down and its list is gone, this one's on its
+        // own
+        let Ok(nested) = PENDING.try_with(|pending| pending.borrow().is_some()) else {
+            drop((next, thunk));
+            return;
+        };
+        if nested {
+            let cell: Box<dyn Pending> = Box::new((next, thunk));
+            PENDING.with(|pending| pending.borrow_mut().as_mut().unwrap().push(cell));
+            return;
+        }
+
+        PENDING.with(|pending| *pending.borrow_mut() = Some(Vec::new()));
+        let _draining = Draining;
+
+        // A long forced stream is a long chain of cells, follow it in a loop
+        drop(thunk);
+        loop {
+            let stream = match next {
+                Some(State::Cons(_, stream)) | Some(State::Same(stream)) => stream,
+                _ => break,
+            };
+            match Rc::try_unwrap(stream.cell) {
+                Ok(mut lazy) => next = lazy.value.take(),
+                Err(_) => break,
+            }
+        }
+
+        // Dropping these can put more on the list, keep going until it stays
+        // empty
+        loop {
+            let cell = PENDING.with(|pending| pending.borrow_mut().as_mut().unwrap().pop());
+            match cell {
+                Some(cell) => drop(cell),
+                None => break,
+            }
+        }
+    }
+}
+
+// Cloning is O(1), the clone shares every cell, forced or not.
+impl<T: 'static> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Stream {
+            cell: self.cell.clone(),
+        }
+    }
+}
+
+impl<T: 'static> Default for Stream<T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+// Forces the cells as it goes.
+pub struct Iter<'a, T: 'static> {
+    next: Option<&'a Stream<T>>,
+}
+
+impl<'a, T: 'static> IntoIterator for &'a Stream<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T: 'static> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (elem, rest) = self.next?.force()?;
+        self.next = Some(rest);
+        Some(elem)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Stream;
+    use std::{cell::Cell, rc::Rc};
+
+    fn nats_from(n: u64) -> Stream<u64> {
+        Stream::cons(n, move || nats_from(n + 1))
+    }
+
+    fn first<T: Clone + 'static>(stream: &Stream<T>, n: usize) -> Vec<T> {
+        stream.take(n).iter().cloned().collect()
+    }
+
+    #[test]
+    fn basics() {
+        let empty = Stream::<i32>::empty();
+        assert_eq!(empty.head(), None);
+        assert!(empty.is_empty());
+        assert!(empty.tail().is_empty());
+
+        let stream = Stream::cons(1, || Stream::cons(2, Stream::empty));
+        assert_eq!(stream.head(), Some(&1));
+        assert_eq!(stream.tail().head(), Some(&2));
+        assert!(stream.tail().tail().is_empty());
+        assert_eq!(stream.iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+
+        let mut seen = Vec::new();
+        for elem in &stream {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![1, 2]);
+    }
+
+    #[test]
+    fn infinite() {
+        let nats = nats_from(0);
+        assert_eq!(first(&nats, 5), vec![0, 1, 2, 3, 4]);
+        assert_eq!(first(&nats.tail().tail(), 3), vec![2, 3, 4]);
+
+        let squares = nats.map(|n| n * n);
+        assert_eq!(first(&squares, 5), vec![0, 1, 4, 9, 16]);
+
+        let odd = nats.filter(|n| n % 2 == 1);
+        assert_eq!(first(&odd, 4), vec![1, 3, 5, 7]);
+
+        let powers = Stream::iterate(1, |n| n * 2);
+        assert_eq!(first(&powers, 6), vec![1, 2, 4, 8, 16, 32]);
+
+        let fib = Stream::unfold((0u64, 1u64), |(a, b)| Some((a, (b, a + b))));
+        assert_eq!(first(&fib, 8), vec![0, 1, 1, 2, 3, 5, 8, 13]);
+
+        let countdown = Stream::unfold(3, |n| (n > 0).then(|| (n, n - 1)));
+        assert_eq!(
+            countdown.to_list().iter().copied().collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert_eq!(nats.take(3).to_list().head(), Some(&0));
+    }
+
+    #[test]
+    fn forced_once() {
+        // Counts how many times each element gets computed
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let stream = nats_from(0).map(move |n| {
+            counter.set(counter.get() + 1);
+            n * 10
+        });
+        assert_eq!(calls.get(), 0);
+
+        let copy = stream.clone();
+        let tail = stream.tail().tail();
+        assert_eq!(calls.get(), 2);
+
+        // Every version sees the same cells, nothing is computed twice
+        assert_eq!(first(&copy, 3), vec![0, 10, 20]);
+        assert_eq!(tail.head(), Some(&20));
+        assert_eq!(first(&stream, 3), vec![0, 10, 20]);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn lazy_streams_are_taken_over() {
+        // A suspension that returns another suspension, many times over
+        let mut stream = Stream::cons(0, Stream::empty);
+        for i in 1..100 {
+            let inner = stream;
+            stream = Stream::lazy(move || Stream::cons(i, move || inner));
+        }
+        assert_eq!(stream.iter().count(), 100);
+
+        // A shared stream is pointed to instead
+        let shared = nats_from(5);
+        let alias = {
+            let shared = shared.clone();
+            Stream::lazy(move || shared)
+        };
+        assert_eq!(alias.head(), Some(&5));
+        assert_eq!(first(&alias.tail(), 2), vec![6, 7]);
+        assert_eq!(shared.tail().head(), Some(&6));
+    }
+
+    #[test]
+    #[should_panic(expected = "stream depends on its own value")]
+    fn self_reference() {
+        let slot: Rc<Cell<Option<Stream<i32>>>> = Rc::new(Cell::new(None));
+        let inner = slot.clone();
+        let stream = Stream::lazy(move || {
+            let me = inner.take().unwrap();
+            me.tail()
+        });
+        slot.set(Some(stream.clone()));
+        stream.head();
+    }
+
+    #[test]
+    fn streams_dropped_while_dropping() {
+        // Each element's `Drop` builds a stream of its own and drops it,
+        // while the outer stream is still being dropped
+        struct Inner(Rc<Cell<usize>>);
+
+        impl Drop for Inner {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        struct Outer(Rc<Cell<usize>>);
+
+        impl Drop for Outer {
+            fn drop(&mut self) {
+                let next = Inner(self.0.clone());
+                let stream = Stream::cons(Inner(self.0.clone()), move || {
+                    Stream::cons(next, Stream::empty)
+                });
+                drop(stream);
+            }
+        }
+
+        let dropped = Rc::new(Cell::new(0));
+        let mut stream = Stream::empty();
+        for _ in 0..10 {
+            let inner = stream;
+            stream = Stream::cons(Outer(dropped.clone()), move || inner);
+        }
+        drop(stream);
+
+        // The nested drops were put off, but they're all done by now, each
+        // one once
+        assert_eq!(dropped.get(), 20);
+        assert_eq!(Rc::strong_count(&dropped), 1);
+    }
+
+    #[test]
+    fn long_streams() {
+        let n = if cfg!(miri) { 1_000 } else { 1_000_000 };
+
+        // Forcing a million cells and dropping them all at once
+        let nats = nats_from(0);
+        assert_eq!(nats.iter().nth(n), Some(&(n as u64)));
+        drop(nats);
+
+        // Filtering past a million rejected elements in one go
+        let late = nats_from(0).filter(move |&x| x >= n as u64);
+        assert_eq!(late.head(), Some(&(n as u64)));
+
+        // A million cells that were never forced, each one held by the
+        // closure of the one in front of it
+        let mut stream = Stream::empty();
+        for i in 0..n {
+            let inner = stream;
+            stream = Stream::cons(i, move || inner);
+        }
+        let mapped = stream.map(|i| i * 2);
+        assert_eq!(mapped.head(), Some(&(2 * (n - 1))));
+        drop(stream);
+        drop(mapped);
+
+        // Forced part of the way, then left unforced
+        let mut stream = Stream::empty();
+        for i in 0..n {
+            let inner = stream;
+            stream = Stream::lazy(move || Stream::cons(i, move || inner));
+        }
+        assert_eq!(stream.iter().nth(n / 2), Some(&(n / 2 - 1)));
+        drop(stream);
+    }
+}
@@ -17,34 +17,266 @@ The memory layuot for the persistent list looks like this:
     list3 -> X ---+
 */
 
+/*
+The nodes are shared through `Rc` by default. `Rc` can't leave the thread it
+was made on, so for lists that need to be sent to other threads the pointer
+is a type parameter, and `List<T, ArcFamily>` uses `Arc` instead. The
+`PointerFamily` trait below is all the list needs from either.
+
+Holding a `P::Pointer<Node<T, P>>` directly would cost the list its
+covariance: the compiler can't see through the associated type, so it would
+have to treat `List<T>` as invariant in `T`, even with the default `Rc`. The
+links keep the pointer as the raw `NonNull` it turns into instead (`Ptr`
+below), which is covariant just like `Rc<U>` and `Arc<U>` are, and takes up
+the same single word.
+
+`PointerFamily` is sealed. Dropping a list relies on `into_inner` handing a
+node over to exactly one of the threads letting go of it, and `Ptr` on
+`into_raw` and `from_raw` round-tripping, so `Rc` and `Arc` are the only
+families the crate vouches for.
+*/
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
 use std::rc::Rc;
+use std::sync::Arc;
+
+mod intern;
 
-pub struct List<T> {
-    head: Link<T>,
+pub use intern::Interner;
+
+pub struct List<T, P: PointerFamily = RcFamily> {
+    head: Link<T, P>,
 }
 
-type Link<T> = Option<Rc<Node<T>>>;
+type Link<T, P> = Option<Ptr<Node<T, P>, P>>;
 
-struct Node<T> {
+struct Node<T, P: PointerFamily> {
     elem: T,
-    next: Link<T>,
+    next: Link<T, P>,
+}
+
+/// A kind of shared pointer, `Rc` or `Arc`, that the list can be built on.
+///
+/// Sealed, only the crate's own families implement it:
+///
+/// ```compile_fail
+/// use too_many_lists::third::PointerFamily;
+/// struct BoxFamily;
+/// impl PointerFamily for BoxFamily {
+///     type Pointer<U> = std::rc::Rc<U>;
+///     fn new<U>(value: U) -> std::rc::Rc<U> { std::rc::Rc::new(value) }
+///     fn try_unwrap<U>(this: std::rc::Rc<U>) -> Result<U, std::rc::Rc<U>> { Err(this) }
+///     fn into_inner<U>(this: std::rc::Rc<U>) -> Option<U> { std::rc::Rc::into_inner(this) }
+///     fn get_mut<U>(this: &mut std::rc::Rc<U>) -> Option<&mut U> { std::rc::Rc::get_mut(this) }
+///     fn strong_count<U>(this: &std::rc::Rc<U>) -> usize { std::rc::Rc::strong_count(this) }
+///     fn into_raw<U>(this: std::rc::Rc<U>) -> *const U { std::rc::Rc::into_raw(this) }
+///     unsafe fn from_raw<U>(ptr: *const U) -> std::rc::Rc<U> { std::rc::Rc::from_raw(ptr) }
+/// }
+/// ```
+pub trait PointerFamily: sealed::Sealed {
+    type Pointer<U>: Deref<Target = U> + Clone;
+
+    fn new<U>(value: U) -> Self::Pointer<U>;
+    fn try_unwrap<U>(this: Self::Pointer<U>) -> Result<U, Self::Pointer<U>>;
+    // Like `try_unwrap`, but if this wasn't the last pointer it's dropped,
+    // and whoever drops the last one is the one that gets the value.
+    fn into_inner<U>(this: Self::Pointer<U>) -> Option<U>;
+    fn get_mut<U>(this: &mut Self::Pointer<U>) -> Option<&mut U>;
+    fn strong_count<U>(this: &Self::Pointer<U>) -> usize;
+    fn into_raw<U>(this: Self::Pointer<U>) -> *const U;
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw` on this family with the same `U`, and
+    /// each pointer given up to `into_raw` can only be taken back once.
+    unsafe fn from_raw<U>(ptr: *const U) -> Self::Pointer<U>;
+}
+
+// Nothing outside the crate can name `Sealed`, so nothing outside the crate
+// can implement `PointerFamily`.
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for super::RcFamily {}
+    impl Sealed for super::ArcFamily {}
+}
+
+pub struct RcFamily;
+
+impl PointerFamily for RcFamily {
+    type Pointer<U> = Rc<U>;
+
+    fn new<U>(value: U) -> Rc<U> {
+        Rc::new(value)
+    }
+
+    fn try_unwrap<U>(this: Rc<U>) -> Result<U, Rc<U>> {
+        Rc::try_unwrap(this)
+    }
+
+    fn into_inner<U>(this: Rc<U>) -> Option<U> {
+        Rc::into_inner(this)
+    }
+
+    fn get_mut<U>(this: &mut Rc<U>) -> Option<&mut U> {
+        Rc::get_mut(this)
+    }
+
+    fn strong_count<U>(this: &Rc<U>) -> usize {
+        Rc::strong_count(this)
+    }
+
+    fn into_raw<U>(this: Rc<U>) -> *const U {
+        Rc::into_raw(this)
+    }
+
+    unsafe fn from_raw<U>(ptr: *const U) -> Rc<U> {
+        Rc::from_raw(ptr)
+    }
+}
+
+pub struct ArcFamily;
+
+impl PointerFamily for ArcFamily {
+    type Pointer<U> = Arc<U>;
+
+    fn new<U>(value: U) -> Arc<U> {
+        Arc::new(value)
+    }
+
+    fn try_unwrap<U>(this: Arc<U>) -> Result<U, Arc<U>> {
+        Arc::try_unwrap(this)
+    }
+
+    fn into_inner<U>(this: Arc<U>) -> Option<U> {
+        Arc::into_inner(this)
+    }
+
+    fn get_mut<U>(this: &mut Arc<U>) -> Option<&mut U> {
+        Arc::get_mut(this)
+    }
+
+    fn strong_count<U>(this: &Arc<U>) -> usize {
+        Arc::strong_count(this)
+    }
+
+    fn into_raw<U>(this: Arc<U>) -> *const U {
+        Arc::into_raw(this)
+    }
+
+    unsafe fn from_raw<U>(ptr: *const U) -> Arc<U> {
+        Arc::from_raw(ptr)
+    }
+}
+
+// A `P::Pointer<U>` kept as the raw pointer it turns into, so that `List` is
+// covariant in `T` like the `Rc<U>` or `Arc<U>` it stands for. It owns one
+// strong count, and goes back to being a real pointer for anything that
+// touches the counts.
+struct Ptr<U, P: PointerFamily> {
+    raw: NonNull<U>,
+    _family: PhantomData<P>,
+}
+
+// Whenever the real pointer could be sent or shared
+unsafe impl<U, P: PointerFamily> Send for Ptr<U, P> where P::Pointer<U>: Send {}
+unsafe impl<U, P: PointerFamily> Sync for Ptr<U, P> where P::Pointer<U>: Sync {}
+
+impl<U, P: PointerFamily> Ptr<U, P> {
+    fn new(value: U) -> Self {
+        Ptr::from_pointer(P::new(value))
+    }
+
+    fn from_pointer(pointer: P::Pointer<U>) -> Self {
+        Ptr {
+            // `Rc` and `Arc` never hand out null
+            raw: unsafe { NonNull::new_unchecked(P::into_raw(pointer) as *mut U) },
+            _family: PhantomData,
+        }
+    }
+
+    fn into_pointer(self) -> P::Pointer<U> {
+        let this = ManuallyDrop::new(self);
+        unsafe { P::from_raw(this.raw.as_ptr()) }
+    }
+
+    // The real pointer, borrowed. It's never dropped, so the counts are left
+    // as they are.
+    fn pointer(&self) -> ManuallyDrop<P::Pointer<U>> {
+        ManuallyDrop::new(unsafe { P::from_raw(self.raw.as_ptr()) })
+    }
+
+    fn as_ptr(this: &Self) -> *const U {
+        this.raw.as_ptr()
+    }
+
+    fn try_unwrap(this: Self) -> Result<U, Self> {
+        P::try_unwrap(this.into_pointer()).map_err(Ptr::from_pointer)
+    }
+
+    fn into_inner(this: Self) -> Option<U> {
+        P::into_inner(this.into_pointer())
+    }
+
+    fn get_mut(this: &mut Self) -> Option<&mut U> {
+        // Unique or not, that's only up to the counts, which `this` keeps a
+        // share of for as long as it's borrowed
+        let mut pointer = this.pointer();
+        P::get_mut(&mut pointer).map(|value| unsafe { &mut *(value as *mut U) })
+    }
+
+    fn strong_count(this: &Self) -> usize {
+        P::strong_count(&this.pointer())
+    }
 }
 
+impl<U, P: PointerFamily> Deref for Ptr<U, P> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { self.raw.as_ref() }
+    }
+}
+
+impl<U, P: PointerFamily> Clone for Ptr<U, P> {
+    fn clone(&self) -> Self {
+        Ptr::from_pointer((*self.pointer()).clone())
+    }
+}
+
+impl<U, P: PointerFamily> Drop for Ptr<U, P> {
+    fn drop(&mut self) {
+        drop(unsafe { P::from_raw(self.raw.as_ptr()) });
+    }
+}
+
+// Type parameter defaults don't take part in inference, so a generic `new`
+// would leave `List::new()` with no idea which family to use. It's only
+// defined for `Rc`, lists on other families start from `default()` (or
+// `arc_list::new()`).
 impl<T> List<T> {
     pub fn new() -> Self {
         List { head: None }
     }
+}
 
-    pub fn prepend(&self, elem: T) -> List<T> {
+impl<T, P: PointerFamily> List<T, P> {
+    pub fn prepend(&self, elem: T) -> Self {
         List {
-            head: Some(Rc::new(Node {
+            head: Some(Ptr::new(Node {
                 elem,
                 next: self.head.clone(),
             })),
         }
     }
 
-    pub fn tail(&self) -> List<T> {
+    pub fn tail(&self) -> Self {
         List {
             head: self.head.as_ref().and_then(|node| node.next.clone()),
         }
@@ -55,19 +287,260 @@ impl<T> List<T> {
     }
 }
 
-pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+// Looking at how versions share their nodes. Two lists share a tail from the
+// first node they have in common to the end, and never before it:
+//
+//    a -> A -> B ---+
+//                   v
+//    b ------> X -> C -> D
+//                   ^
+//                   common suffix
+impl<T, P: PointerFamily> List<T, P> {
+    // Whether both lists start at the same node, so they're the same version
+    // (two empty lists count too).
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b)) => ptr::eq(&**a, &**b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    // Whether the lists have any node in common. If they do, they end in the
+    // same last node.
+    pub fn shares_tail_with(&self, other: &Self) -> bool {
+        match (self.links().last(), other.links().last()) {
+            (Some(a), Some(b)) => ptr::eq(&**a, &**b),
+            _ => false,
+        }
+    }
+
+    // The longest tail both lists share in memory, not just by value.
+    pub fn common_suffix(a: &Self, b: &Self) -> Self {
+        let (a_len, b_len) = (a.links().count(), b.links().count());
+        let mut a_links = a.links().skip(a_len.saturating_sub(b_len));
+        let mut b_links = b.links().skip(b_len.saturating_sub(a_len));
+
+        // Once the lists meet they stay together until the end
+        let head = loop {
+            match (a_links.next(), b_links.next()) {
+                (Some(a), Some(b)) if ptr::eq(&**a, &**b) => break Some(a.clone()),
+                (Some(_), Some(_)) => {}
+                _ => break None,
+            }
+        };
+        List { head }
+    }
+
+    // How many lists and nodes point at each node, from the head down. A
+    // count above one means that node and everything after it is shared.
+    pub fn strong_counts(&self) -> Vec<usize> {
+        self.links().map(Ptr::strong_count).collect()
+    }
+
+    fn links(&self) -> impl Iterator<Item = &Ptr<Node<T, P>, P>> {
+        iter::successors(self.head.as_ref(), |node| node.next.as_ref())
+    }
+}
+
+// Non-destructive operations that build new lists. The nodes of a list can't
+// be changed, so everything in front of the last node an operation changes
+// is copied, and everything after it is shared with the original. None of
+// them recurse, so they're fine on lists of any length.
+impl<T, P: PointerFamily> List<T, P> {
+    // Everything but the first `n` elements, all of it shared.
+    pub fn drop(&self, n: usize) -> Self {
+        List {
+            head: self.links().nth(n).cloned(),
+        }
+    }
+
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> List<U, P> {
+        self.iter().map(f).collect()
+    }
+
+    // Folds from the last element to the first.
+    pub fn fold_right<B>(&self, init: B, mut f: impl FnMut(&T, B) -> B) -> B {
+        let elems: Vec<_> = self.iter().collect();
+        elems.into_iter().rev().fold(init, |acc, elem| f(elem, acc))
+    }
+
+    // A fresh copy of `prefix` in front of all of `suffix`.
+    fn concat(prefix: impl IntoIterator<Item = T>, suffix: &Self) -> Self {
+        let mut list = List::default();
+        let mut tail = &mut list.head;
+        for elem in prefix {
+            let node = tail.insert(Ptr::new(Node { elem, next: None }));
+            tail = &mut Ptr::get_mut(node).unwrap().next;
+        }
+        *tail = suffix.head.clone();
+        list
+    }
 }
 
-impl<T> List<T> {
-    pub fn iter(&self) -> Iter<T> {
+impl<T: Clone, P: PointerFamily> List<T, P> {
+    // Copies this list in front of `other`, which is shared.
+    pub fn append(&self, other: &Self) -> Self {
+        List::concat(self.iter().cloned(), other)
+    }
+
+    pub fn reverse(&self) -> Self {
+        let mut reversed = List::default();
+        for elem in self.iter() {
+            reversed = reversed.prepend(elem.clone());
+        }
+        reversed
+    }
+
+    // Whatever follows the last element filtered out is shared.
+    pub fn filter(&self, mut pred: impl FnMut(&T) -> bool) -> Self {
+        // Kept elements that come before a dropped one, and so need copying
+        let mut copied = Vec::new();
+        // Kept elements since the last dropped one
+        let mut pending = Vec::new();
+        let mut suffix = self.clone();
+
+        for link in self.links() {
+            if pred(&link.elem) {
+                pending.push(&link.elem);
+            } else {
+                copied.append(&mut pending);
+                suffix = List {
+                    head: link.next.clone(),
+                };
+            }
+        }
+        List::concat(copied.into_iter().cloned(), &suffix)
+    }
+
+    // The first `n` elements. If that's all of them nothing changes, and
+    // the whole list is shared.
+    pub fn take(&self, n: usize) -> Self {
+        if self.links().nth(n).is_none() {
+            return self.clone();
+        }
+        self.iter().take(n).cloned().collect()
+    }
+
+    pub fn zip<U: Clone>(&self, other: &List<U, P>) -> List<(T, U), P> {
+        self.iter()
+            .zip(other.iter())
+            .map(|(a, b)| (a.clone(), b.clone()))
+            .collect()
+    }
+
+    // Copies the first `index` elements, the rest is shared. If `index` is
+    // past the end, the element is handed back.
+    pub fn insert_at(&self, index: usize, elem: T) -> Result<Self, T> {
+        let prefix: Vec<_> = self.iter().take(index).collect();
+        if prefix.len() < index {
+            return Err(elem);
+        }
+        let prefix = prefix.into_iter().cloned().chain(iter::once(elem));
+        Ok(List::concat(prefix, &self.drop(index)))
+    }
+}
+
+impl<T, P: PointerFamily> Default for List<T, P> {
+    fn default() -> Self {
+        List { head: None }
+    }
+}
+
+// Collecting builds the list in source order, the first element becomes the
+// head. The nodes are fresh so we can link them up through `get_mut`.
+impl<T, P: PointerFamily> FromIterator<T> for List<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::default();
+        let mut tail = &mut list.head;
+        for elem in iter {
+            let node = tail.insert(Ptr::new(Node { elem, next: None }));
+            tail = &mut Ptr::get_mut(node).unwrap().next;
+        }
+        list
+    }
+}
+
+// Extending replaces this version with one that has every element prepended
+// in turn, the last element becomes the head. Other versions are unaffected.
+impl<T, P: PointerFamily> Extend<T> for List<T, P> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for elem in iter {
+            *self = self.prepend(elem);
+        }
+    }
+}
+
+// Cloning a persistent list is O(1), the clone is just another handle to the
+// same head node.
+impl<T, P: PointerFamily> Clone for List<T, P> {
+    fn clone(&self) -> Self {
+        List {
+            head: self.head.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, P: PointerFamily> fmt::Debug for List<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, P: PointerFamily> PartialEq for List<T, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, P: PointerFamily> Eq for List<T, P> {}
+
+impl<T: PartialOrd, P: PointerFamily> PartialOrd for List<T, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, P: PointerFamily> Ord for List<T, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+// Hashed like `first::List`, the elements and then the length.
+impl<T: Hash, P: PointerFamily> Hash for List<T, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut len = 0;
+        for elem in self.iter() {
+            elem.hash(state);
+            len += 1;
+        }
+        state.write_usize(len);
+    }
+}
+
+pub struct Iter<'a, T, P: PointerFamily = RcFamily> {
+    next: Option<&'a Node<T, P>>,
+}
+
+impl<T, P: PointerFamily> List<T, P> {
+    pub fn iter(&self) -> Iter<'_, T, P> {
         Iter {
             next: self.head.as_deref(),
         }
     }
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
+impl<'a, T, P: PointerFamily> IntoIterator for &'a List<T, P> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+impl<'a, T, P: PointerFamily> Iterator for Iter<'a, T, P> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -78,15 +551,48 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 }
 
-impl<T> Drop for List<T> {
+// There is no `IterMut`, nodes may be shared with other lists so their
+// elements can never be handed out mutably.
+//
+// Consuming a list moves elements out of the nodes this list uniquely owns,
+// and clones the elements of the first shared node onwards.
+pub struct IntoIter<T, P: PointerFamily = RcFamily>(List<T, P>);
+
+impl<T: Clone, P: PointerFamily> IntoIterator for List<T, P> {
+    type Item = T;
+    type IntoIter = IntoIter<T, P>;
+
+    fn into_iter(self) -> IntoIter<T, P> {
+        IntoIter(self)
+    }
+}
+
+impl<T: Clone, P: PointerFamily> Iterator for IntoIter<T, P> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.head.take().map(|node| match Ptr::try_unwrap(node) {
+            Ok(mut node) => {
+                self.0.head = node.next.take();
+                node.elem
+            }
+            Err(node) => {
+                self.0.head = node.next.clone();
+                node.elem.clone()
+            }
+        })
+    }
+}
+
+// Not `try_unwrap`: with `Arc`, two threads dropping the last two pointers
+// to a node at the same time could both see it shared and give up, and
+// whichever drop came last would free the rest of the list recursively.
+// `into_inner` hands the node to exactly one of them.
+impl<T, P: PointerFamily> Drop for List<T, P> {
     fn drop(&mut self) {
         let mut head = self.head.take();
-        while let Some(node) = head {
-            if let Ok(mut node) = Rc::try_unwrap(node) {
-                head = node.next.take();
-            } else {
-                break;
-            }
+        while let Some(mut node) = head.and_then(Ptr::into_inner) {
+            head = node.next.take();
         }
     }
 }
@@ -127,4 +633,218 @@ mod test {
         assert_eq!(iter.next(), Some(&1));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn for_loops() {
+        let list = List::new().prepend(1).prepend(2).prepend(3);
+
+        let mut seen = Vec::new();
+        for elem in &list {
+            seen.push(*elem);
+        }
+        assert_eq!(seen, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn into_iter() {
+        let list = List::new().prepend(1).prepend(2);
+        let shared = list.prepend(3);
+
+        // `3` is unique to `shared` and gets moved out, `2` and `1` are
+        // still reachable through `list` and get cloned.
+        let mut iter = shared.into_iter();
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(list.head(), Some(&2));
+    }
+
+    #[test]
+    fn from_iter() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(list.head(), Some(&1));
+
+        // The collected list shares its nodes like any other version
+        let prepended = list.tail().prepend(4);
+        assert_eq!(prepended.iter().copied().collect::<Vec<_>>(), vec![4, 2, 3]);
+    }
+
+    #[test]
+    fn extend() {
+        let original: List<_> = vec![1, 2].into_iter().collect();
+        let mut list = original.tail();
+        list.extend(vec![3, 4]);
+
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![4, 3, 2]);
+        assert_eq!(original.iter().copied().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn std_traits() {
+        let list: List<_> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert!(list < list.tail());
+
+        // Cloning only bumps the count on the head
+        let copy = list.clone();
+        assert!(copy.ptr_eq(&list));
+        assert_eq!(copy, list);
+    }
+
+    #[test]
+    fn variance() {
+        use super::{ArcFamily, Iter};
+
+        fn list_covariant<'a, T: ?Sized>(x: List<&'static T>) -> List<&'a T> {
+            x
+        }
+        fn arc_list_covariant<'a, T: ?Sized>(
+            x: List<&'static T, ArcFamily>,
+        ) -> List<&'a T, ArcFamily> {
+            x
+        }
+        fn iter_covariant<'i, 'a, T: ?Sized>(x: Iter<'i, &'static T>) -> Iter<'i, &'a T> {
+            x
+        }
+
+        // A list of `'static` strings shortened to a local one's lifetime can
+        // have that one prepended
+        let list: List<&'static str> = vec!["b", "c"].into_iter().collect();
+        let local = String::from("a");
+        let longer = list_covariant(list.clone()).prepend(&local);
+        assert_eq!(longer.iter().count(), 3);
+        assert_eq!(iter_covariant::<str>(list.iter()).count(), 2);
+        assert!(longer.tail().ptr_eq(&list));
+
+        let arc_list: List<&'static str, ArcFamily> = list.iter().copied().collect();
+        assert_eq!(
+            arc_list_covariant(arc_list).prepend(&local).head(),
+            Some(&"a")
+        );
+    }
+
+    #[test]
+    fn sharing() {
+        let list1: List<_> = vec!['A', 'B', 'C', 'D'].into_iter().collect();
+        let list2 = list1.tail();
+        let list3 = list2.prepend('X');
+
+        assert!(list1.ptr_eq(&list1.clone()));
+        assert!(!list1.ptr_eq(&list3));
+        assert!(list1.shares_tail_with(&list3));
+        assert!(List::<char>::new().ptr_eq(&List::new()));
+
+        // `B -> C -> D` is the tail all three have in common
+        let common = List::common_suffix(&list1, &list3);
+        assert!(common.ptr_eq(&list2));
+        assert!(List::common_suffix(&list3, &list2).ptr_eq(&list2));
+
+        // `B` is pointed at by `A`, `X` and `list2`, and now `common` too
+        assert_eq!(list1.strong_counts(), vec![1, 4, 1, 1]);
+        assert_eq!(list3.strong_counts(), vec![1, 4, 1, 1]);
+    }
+
+    #[test]
+    fn equal_but_not_shared() {
+        let a: List<_> = (0..4).collect();
+        let b: List<_> = (0..4).collect();
+        assert_eq!(a, b);
+        assert!(!a.ptr_eq(&b));
+        assert!(!a.shares_tail_with(&b));
+        assert!(List::common_suffix(&a, &b).head().is_none());
+        assert!(!a.shares_tail_with(&List::new()));
+
+        // Sharing starts wherever the versions last branched
+        let base: List<_> = (10..13).collect();
+        let mut a = base.clone();
+        let mut b = base.tail();
+        a.extend(0..5);
+        b.extend(0..2);
+        let common = List::common_suffix(&a, &b);
+        assert!(common.ptr_eq(&base.tail()));
+        assert_eq!(b.strong_counts(), vec![1, 1, 3, 1]);
+    }
+
+    #[test]
+    fn combinators() {
+        let list: List<_> = (1..=5).collect();
+        let contents = |list: &List<i32>| list.iter().copied().collect::<Vec<_>>();
+
+        let tail: List<_> = (6..=7).collect();
+        let appended = list.append(&tail);
+        assert_eq!(contents(&appended), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert!(appended.drop(5).ptr_eq(&tail));
+
+        assert_eq!(contents(&list.reverse()), vec![5, 4, 3, 2, 1]);
+        assert_eq!(contents(&list.map(|x| x * 10)), vec![10, 20, 30, 40, 50]);
+        assert_eq!(contents(&list.take(2)), vec![1, 2]);
+        assert!(list.take(10).ptr_eq(&list));
+        assert!(list.take(5).ptr_eq(&list));
+        assert!(!list.take(4).ptr_eq(&list));
+        assert_eq!(contents(&list.drop(3)), vec![4, 5]);
+        assert!(list.drop(10).head().is_none());
+
+        let zipped = list.zip(&list.map(|x| x.to_string()));
+        assert_eq!(zipped.head(), Some(&(1, "1".to_string())));
+        assert_eq!(zipped.iter().count(), 5);
+
+        let digits = list.fold_right(String::new(), |x, acc| acc + &x.to_string());
+        assert_eq!(digits, "54321");
+
+        // The original is untouched by all of it
+        assert_eq!(contents(&list), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn combinators_share_suffixes() {
+        let list: List<_> = (1..=6).collect();
+
+        // Everything after the last element filtered out is shared
+        let odd = list.filter(|x| x % 2 == 1);
+        assert_eq!(odd.iter().copied().collect::<Vec<_>>(), vec![1, 3, 5]);
+        assert!(!odd.shares_tail_with(&list));
+
+        let small = list.filter(|&x| x != 2);
+        assert_eq!(
+            small.iter().copied().collect::<Vec<_>>(),
+            vec![1, 3, 4, 5, 6]
+        );
+        assert!(small.drop(1).ptr_eq(&list.drop(2)));
+
+        let all = list.filter(|_| true);
+        assert!(all.ptr_eq(&list));
+        assert!(list.filter(|_| false).head().is_none());
+
+        let inserted = list.insert_at(2, 0).unwrap();
+        assert_eq!(
+            inserted.iter().copied().collect::<Vec<_>>(),
+            vec![1, 2, 0, 3, 4, 5, 6]
+        );
+        assert!(inserted.drop(3).ptr_eq(&list.drop(2)));
+        assert!(list.insert_at(6, 7).unwrap().drop(7).head().is_none());
+        assert_eq!(list.insert_at(7, 7).err(), Some(7));
+        assert!(List::new().insert_at(0, 1).is_ok());
+    }
+
+    #[test]
+    fn combinators_on_long_lists() {
+        let n = if cfg!(miri) { 1_000 } else { 1_000_000 };
+        let list: List<_> = (0..n).collect();
+
+        let reversed = list.reverse();
+        assert_eq!(reversed.head(), Some(&(n - 1)));
+        let appended = list.append(&reversed);
+        assert_eq!(appended.drop(2 * n - 1).head(), Some(&0));
+        let mapped = list.map(|x| x + 1);
+        let filtered = mapped.filter(|x| x % 2 == 0);
+        assert_eq!(filtered.head(), Some(&2));
+        let zipped = list.zip(&filtered);
+        assert_eq!(zipped.drop(n / 2 - 1).head(), Some(&(n / 2 - 1, n)));
+        assert_eq!(list.fold_right(0, |_, count| count + 1), n);
+        let inserted = list.insert_at(n - 1, n).unwrap();
+        assert_eq!(inserted.take(n).drop(n - 1).head(), Some(&n));
+    }
 }
@@ -0,0 +1,296 @@
+/*
+Hash-consing
+------------
+
+Lists built independently never share nodes, even when they hold the same
+elements:
+
+    a -> 1 -> 2 -> 3
+    b -> 0 -> 2 -> 3
+
+An `Interner` hands out lists where every node is the only one of its kind:
+before making a node for `elem` in front of `tail`, it looks in a table for
+an existing node with an equal element in front of that same tail node, and
+reuses it if there is one. Built through the interner, those two lists end
+in the same `2 -> 3`:
+
+    a -> 1 ---+
+              |
+              v
+              2 -> 3
+              ^
+              |
+    b -> 0 ---+
+
+Since every tail is unique too, two interned lists are equal exactly when
+they start at the same node, so `ptr_eq` compares them in O(1).
+
+The table is keyed by the element's hash and the address of the tail node,
+and only holds `Weak` pointers, so it never keeps a list alive, tails
+included. Once a tail is freed its address can be reused while a dead entry
+is still filed under it, so the key only narrows the search down. A lookup
+upgrades each entry first, and a node that's still alive keeps its own tail
+alive, so comparing that tail with `ptr_eq` can't be fooled by a reused
+address. Entries whose nodes have been freed are swept out whenever the
+table has doubled in size since the last sweep.
+*/
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    hash::{BuildHasher, Hash, RandomState},
+    rc::{Rc, Weak},
+};
+
+use super::{List, Node, Ptr, RcFamily};
+
+type Entry<T> = Weak<Node<T, RcFamily>>;
+
+pub struct Interner<T> {
+    table: RefCell<Table<T>>,
+    hasher: RandomState,
+}
+
+struct Table<T> {
+    // Keyed by the element's hash and the tail's address, nodes whose
+    // elements collide share a bucket
+    buckets: HashMap<(u64, usize), Vec<Entry<T>>>,
+    entries: usize,
+    // Sweep out dead entries once `entries` gets past this
+    limit: usize,
+}
+
+const MIN_LIMIT: usize = 64;
+
+impl<T: Hash + Eq> Interner<T> {
+    pub fn new() -> Self {
+        Interner {
+            table: RefCell::new(Table {
+                buckets: HashMap::new(),
+                entries: 0,
+                limit: MIN_LIMIT,
+            }),
+            hasher: RandomState::new(),
+        }
+    }
+
+    // Like `List::prepend`. If `tail` came from this interner so does the
+    // result, and it's the same node every time for the same arguments.
+    pub fn prepend(&self, tail: &List<T>, elem: T) -> List<T> {
+        let key = self.key(&elem, tail);
+        if let Some(list) = self.find(key, &elem, tail) {
+            return list;
+        }
+        let list = tail.prepend(elem);
+        self.insert(key, &list);
+        list
+    }
+
+    // The interned version of `list`. Elements are only copied for nodes
+    // the interner hasn't seen yet, and a list that's already interned comes
+    // back as it is.
+    pub fn intern(&self, list: &List<T>) -> List<T>
+    where
+        T: Clone,
+    {
+        let elems: Vec<_> = list.iter().collect();
+        let mut interned = List::new();
+        for elem in elems.into_iter().rev() {
+            let key = self.key(elem, &interned);
+            interned = match self.find(key, elem, &interned) {
+                Some(found) => found,
+                None => {
+                    let new = interned.prepend(elem.clone());
+                    self.insert(key, &new);
+                    new
+                }
+            };
+        }
+        interned
+    }
+
+    // How many entries the table holds, dead ones included until the next
+    // sweep.
+    pub fn len(&self) -> usize {
+        self.table.borrow().entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Drops the entries for nodes that have been freed.
+    pub fn purge(&self) {
+        self.table.borrow_mut().sweep();
+    }
+
+    fn key(&self, elem: &T, tail: &List<T>) -> (u64, usize) {
+        let tail = tail
+            .head
+            .as_ref()
+            .map_or(0, |node| Ptr::as_ptr(node) as usize);
+        (self.hasher.hash_one(elem), tail)
+    }
+
+    fn find(&self, key: (u64, usize), elem: &T, tail: &List<T>) -> Option<List<T>> {
+        let table = self.table.borrow();
+        let head = table.buckets.get(&key)?.iter().find_map(|entry| {
+            let node = entry.upgrade()?;
+            let same_tail = List {
+                head: node.next.clone(),
+            }
+            .ptr_eq(tail);
+            (same_tail && node.elem == *elem).then_some(node)
+        })?;
+        Some(List {
+            head: Some(Ptr::from_pointer(head)),
+        })
+    }
+
+    fn insert(&self, key: (u64, usize), list: &List<T>) {
+        let mut table = self.table.borrow_mut();
+        let node = list.head.as_ref().unwrap();
+        table
+            .buckets
+            .entry(key)
+            .or_default()
+            .push(Rc::downgrade(&node.pointer()));
+        table.entries += 1;
+        if table.entries > table.limit {
+            table.sweep();
+        }
+    }
+}
+
+impl<T> Table<T> {
+    fn sweep(&mut self) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|entry| entry.strong_count() > 0);
+            !bucket.is_empty()
+        });
+        self.entries = self.buckets.values().map(Vec::len).sum();
+        self.limit = (self.entries * 2).max(MIN_LIMIT);
+    }
+}
+
+impl<T: Hash + Eq> Default for Interner<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Interner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Interner")
+            .field("entries", &self.table.borrow().entries)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Interner;
+    use crate::third::List;
+    use std::hash::{Hash, Hasher};
+
+    #[test]
+    fn equal_tails_are_shared() {
+        let interner = Interner::new();
+        let a = interner.intern(&List::new().prepend(3).prepend(2).prepend(1));
+        let b = interner.intern(&List::new().prepend(3).prepend(2).prepend(0));
+
+        // Built separately, but the `2 -> 3` is the same nodes
+        assert!(!a.ptr_eq(&b));
+        assert!(a.tail().ptr_eq(&b.tail()));
+        assert_eq!(interner.len(), 4);
+
+        // Equal interned lists are the same list
+        let c = interner.prepend(&interner.prepend(&interner.prepend(&List::new(), 3), 2), 1);
+        assert!(c.ptr_eq(&a));
+        assert_eq!(c, a);
+        assert!(interner.intern(&a).ptr_eq(&a));
+        assert_eq!(interner.len(), 4);
+
+        // A tail that's equal but not interned isn't the same tail
+        let plain = List::new().prepend(3).prepend(2);
+        let d = interner.prepend(&plain, 1);
+        assert!(!d.ptr_eq(&a));
+        assert_eq!(d, a);
+
+        assert!(interner.intern(&List::new()).ptr_eq(&List::new()));
+    }
+
+    #[test]
+    fn entries_are_weak() {
+        let interner = Interner::new();
+        let list = interner.intern(&(0..10).collect());
+        assert_eq!(interner.len(), 10);
+
+        // The interner doesn't count as an owner
+        assert_eq!(list.strong_counts(), vec![1; 10]);
+
+        let tail = list.drop(5);
+        drop(list);
+        interner.purge();
+        assert_eq!(interner.len(), 5);
+
+        // Interning the same thing again makes new nodes for the freed part
+        // and finds the part that's still alive
+        let again = interner.intern(&(0..10).collect());
+        assert!(again.drop(5).ptr_eq(&tail));
+        assert_eq!(interner.len(), 10);
+
+        drop((again, tail));
+        interner.purge();
+        assert!(interner.is_empty());
+        assert_eq!(format!("{:?}", interner), "Interner { entries: 0 }");
+    }
+
+    #[test]
+    fn hash_collisions() {
+        // Every value hashes the same, so they all land in one bucket
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        struct Clash(u32);
+        impl Hash for Clash {
+            fn hash<H: Hasher>(&self, _: &mut H) {}
+        }
+
+        let interner = Interner::new();
+        let empty = List::new();
+        let lists: Vec<_> = (0..10)
+            .map(|i| interner.prepend(&empty, Clash(i)))
+            .collect();
+        for (i, list) in lists.iter().enumerate() {
+            let again = interner.prepend(&empty, Clash(i as u32));
+            assert!(again.ptr_eq(list));
+            assert_eq!(again.head(), Some(&Clash(i as u32)));
+        }
+        assert_eq!(interner.len(), 10);
+    }
+
+    #[test]
+    fn dead_entries_are_swept() {
+        let interner = Interner::new();
+        let empty = List::new();
+        for i in 0..10_000 {
+            interner.prepend(&empty, i);
+        }
+        // Nothing is alive, so the table never grows far past its minimum
+        assert!(interner.len() <= 2 * super::MIN_LIMIT);
+    }
+
+    #[test]
+    fn long_list() {
+        let n = if cfg!(miri) { 1_000 } else { 100_000 };
+        let interner = Interner::new();
+        let a = interner.intern(&(0..n).collect());
+        let b = interner.intern(&(0..n).collect());
+        assert!(a.ptr_eq(&b));
+
+        // Only the new head is a new node
+        let c = interner.intern(&a.prepend(-1));
+        assert!(c.tail().ptr_eq(&a));
+        assert_eq!(interner.len(), n as usize + 1);
+    }
+}
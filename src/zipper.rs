@@ -0,0 +1,243 @@
+/*
+A zipper over a persistent list
+-------------------------------
+
+A position inside a `third::List` that can be moved and edited in O(1). The
+list is cut in two at the focus: the elements after it, starting with the
+focus itself, stay a plain list, and the elements before it are kept in a
+second list, in reverse so the one next to the focus is at its head:
+
+    list:     A B C D E
+    focus:        ^
+    before:   B -> A
+    after:    C -> D -> E
+
+Moving right takes the head of `after` and puts it on `before`, moving left
+does the opposite, and edits only ever touch the heads of the two lists.
+Nothing is ever copied except the element that moves, and like any
+persistent structure every version of the zipper stays valid.
+
+Only `to_list` has to do real work: it puts `before` back in front of
+`after`, copying the elements before the focus and sharing the rest.
+*/
+
+use std::fmt;
+
+use crate::third::{List, PointerFamily, RcFamily};
+
+pub struct Zipper<T, P: PointerFamily = RcFamily> {
+    // Elements before the focus, nearest first
+    before: List<T, P>,
+    // The focus and everything after it
+    after: List<T, P>,
+    index: usize,
+}
+
+impl<T, P: PointerFamily> Zipper<T, P> {
+    // Focuses on the first element of `list`.
+    pub fn new(list: &List<T, P>) -> Self {
+        Zipper {
+            before: List::default(),
+            after: list.clone(),
+            index: 0,
+        }
+    }
+
+    // `None` when the zipper is past the last element.
+    pub fn focus(&self) -> Option<&T> {
+        self.after.head()
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn is_at_start(&self) -> bool {
+        self.index == 0
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.after.head().is_none()
+    }
+
+    // Puts `elem` in at the focus, moving the old focus and everything
+    // after it one to the right. `elem` becomes the focus.
+    pub fn insert(&self, elem: T) -> Self {
+        Zipper {
+            before: self.before.clone(),
+            after: self.after.prepend(elem),
+            index: self.index,
+        }
+    }
+
+    pub fn replace(&self, elem: T) -> Result<Self, T> {
+        if self.is_at_end() {
+            return Err(elem);
+        }
+        Ok(Zipper {
+            before: self.before.clone(),
+            after: self.after.tail().prepend(elem),
+            index: self.index,
+        })
+    }
+
+    // Removes the focus, the element after it becomes the new focus.
+    pub fn delete(&self) -> Option<Self> {
+        if self.is_at_end() {
+            return None;
+        }
+        Some(Zipper {
+            before: self.before.clone(),
+            after: self.after.tail(),
+            index: self.index,
+        })
+    }
+}
+
+impl<T: Clone, P: PointerFamily> Zipper<T, P> {
+    pub fn left(&self) -> Option<Self> {
+        let elem = self.before.head()?;
+        Some(Zipper {
+            before: self.before.tail(),
+            after: self.after.prepend(elem.clone()),
+            index: self.index - 1,
+        })
+    }
+
+    // Can move one past the last element, where there's no focus and
+    // `insert` appends.
+    pub fn right(&self) -> Option<Self> {
+        let elem = self.after.head()?;
+        Some(Zipper {
+            before: self.before.prepend(elem.clone()),
+            after: self.after.tail(),
+            index: self.index + 1,
+        })
+    }
+
+    // Copies the elements before the focus, the rest is shared.
+    pub fn to_list(&self) -> List<T, P> {
+        let mut list = self.after.clone();
+        for elem in self.before.iter() {
+            list = list.prepend(elem.clone());
+        }
+        list
+    }
+}
+
+// Like the lists it's made of, cloning is O(1).
+impl<T, P: PointerFamily> Clone for Zipper<T, P> {
+    fn clone(&self) -> Self {
+        Zipper {
+            before: self.before.clone(),
+            after: self.after.clone(),
+            index: self.index,
+        }
+    }
+}
+
+impl<T: fmt::Debug, P: PointerFamily> fmt::Debug for Zipper<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut before: Vec<_> = self.before.iter().collect();
+        before.reverse();
+        f.debug_struct("Zipper")
+            .field("before", &before)
+            .field("after", &self.after)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Zipper;
+    use crate::third::List;
+
+    fn contents(zipper: &Zipper<i32>) -> Vec<i32> {
+        zipper.to_list().iter().copied().collect()
+    }
+
+    #[test]
+    fn moving_around() {
+        let list: List<_> = (1..=3).collect();
+        let zipper = Zipper::new(&list);
+        assert_eq!(zipper.focus(), Some(&1));
+        assert!(zipper.is_at_start());
+        assert!(zipper.left().is_none());
+
+        let zipper = zipper.right().unwrap().right().unwrap();
+        assert_eq!(zipper.focus(), Some(&3));
+        assert_eq!(zipper.index(), 2);
+
+        // One past the end there's nothing in focus
+        let end = zipper.right().unwrap();
+        assert!(end.is_at_end());
+        assert_eq!(end.focus(), None);
+        assert!(end.right().is_none());
+
+        let zipper = end.left().unwrap().left().unwrap();
+        assert_eq!(zipper.focus(), Some(&2));
+        assert_eq!(contents(&zipper), vec![1, 2, 3]);
+        assert_eq!(
+            format!("{:?}", zipper),
+            "Zipper { before: [1], after: [2, 3] }"
+        );
+    }
+
+    #[test]
+    fn editing() {
+        let list: List<_> = (1..=4).collect();
+        let zipper = Zipper::new(&list).right().unwrap();
+
+        let replaced = zipper.replace(20).unwrap();
+        assert_eq!(contents(&replaced), vec![1, 20, 3, 4]);
+
+        let inserted = zipper.insert(10);
+        assert_eq!(inserted.focus(), Some(&10));
+        assert_eq!(contents(&inserted), vec![1, 10, 2, 3, 4]);
+
+        let deleted = zipper.delete().unwrap();
+        assert_eq!(deleted.focus(), Some(&3));
+        assert_eq!(contents(&deleted), vec![1, 3, 4]);
+
+        // At the end, insert appends and there's nothing to replace or delete
+        let end = (0..3).fold(zipper.clone(), |z, _| z.right().unwrap());
+        assert!(end.is_at_end());
+        assert_eq!(contents(&end.insert(5)), vec![1, 2, 3, 4, 5]);
+        assert_eq!(end.replace(5).err(), Some(5));
+        assert!(end.delete().is_none());
+
+        // Every version is still what it was
+        assert_eq!(contents(&zipper), vec![1, 2, 3, 4]);
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn suffix_is_shared() {
+        let list: List<_> = (0..100).collect();
+        let zipper = Zipper::new(&list).right().unwrap().right().unwrap();
+        let edited = zipper.replace(-1).unwrap().to_list();
+
+        // Only the first three elements are new
+        assert_eq!(
+            edited.take(3).iter().copied().collect::<Vec<_>>(),
+            vec![0, 1, -1]
+        );
+        assert!(edited.drop(3).ptr_eq(&list.drop(3)));
+        assert!(Zipper::new(&list).to_list().ptr_eq(&list));
+    }
+
+    #[test]
+    fn long_walk() {
+        let n = if cfg!(miri) { 1_000 } else { 1_000_000 };
+        let list: List<_> = (0..n).collect();
+
+        // Walk to the end rewriting every element on the way
+        let mut zipper = Zipper::new(&list);
+        while let Some(&elem) = zipper.focus() {
+            zipper = zipper.replace(elem * 2).unwrap().right().unwrap();
+        }
+        let doubled = zipper.to_list();
+        assert_eq!(doubled.drop(n - 1).head(), Some(&((n - 1) * 2)));
+        assert_eq!(list.drop(n - 1).head(), Some(&(n - 1)));
+    }
+}